@@ -1,20 +1,52 @@
 //! Google Drive API
 
-use serde::{Serialize, Deserialize};
-use lazy_static::lazy_static;
-use std::sync::{Arc, Mutex};
-use std::cell::Cell;
+use serde::{Serialize, Deserialize, Deserializer};
+use std::sync::Arc;
 use std::path::Path;
-use reqwest::blocking::multipart::{Form, Part};
-use crate::api::GoogleResponse;
-use crate::api::oauth::get_access_token;
-
-use crate::{Result, unwrap_req_err, unwrap_google_err, unwrap_other_err, Error};
+use reqwest::multipart::{Form, Part};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use crate::api::{self, GoogleResponse};
+use crate::api::oauth::TokenProvider;
+use crate::api::resumable::{self, UploadRequest, RESUMABLE_THRESHOLD};
+use crate::api::retry;
+use crate::backend;
+
+use crate::{Result, ErrorKind, unwrap_req_err, unwrap_google_err, unwrap_other_err, unwrap_io_err, new_err};
 use crate::env::Env;
 
-lazy_static! {
-    /// Vector of IDs that can be used for creating files and folders
-    static ref IDS: Arc<Mutex<Cell<Vec<String>>>> = Arc::new(Mutex::new(Cell::new(Vec::new())));
+/// The MIME type Google Drive uses for folders
+pub const FOLDER_MIME_TYPE: &str = "application/vnd.google-apps.folder";
+
+/// The base URL of the Google APIs
+const GOOGLE_APIS_URL: &str = "https://www.googleapis.com";
+
+/// The environment variable overriding `GOOGLE_APIS_URL`
+const GOOGLE_APIS_URL_VAR: &str = "GSYNC_GOOGLE_APIS_URL";
+
+/// The maximum page size Google allows when listing files
+const MAX_FILES_PAGE_SIZE: u32 = 1000;
+
+/// The maximum page size Google allows when listing shared drives
+const MAX_DRIVES_PAGE_SIZE: u32 = 100;
+
+/// Client for the Google Drive API. All requests share one connection pool, cloning the client is cheap and shares the pool as well
+#[derive(Clone)]
+pub struct DriveClient {
+    /// The HTTP client all requests are sent with
+    http:           reqwest::Client,
+
+    /// The base URL of the Google APIs, without a trailing slash
+    base_url:       String,
+
+    /// Provides the access tokens requests are authorized with
+    tokens:         Arc<TokenProvider>,
+
+    /// Env instance
+    env:            Env,
+
+    /// IDs that can be used for creating files and folders
+    ids:            Arc<Mutex<Vec<String>>>
 }
 
 /// Struct describing the metadata supplied when creating a file
@@ -31,93 +63,6 @@ struct CreateFileRequestMetadata<'a> {
     parents:    Vec<&'a str>
 }
 
-/// Create a folder in Google Drive, and return it's ID
-///
-/// ## Params
-/// - `env` Env instance
-/// - `folder_name` The name of the folder to create
-/// - `parent` ID of parent folder
-///
-/// ## Errors
-/// - Request failure
-/// - Google API error
-pub fn create_folder(env: &Env, folder_name: &str, parent: &str) -> Result<String> {
-    let access_token = get_access_token(env)?;
-    let id = get_id(env)?;
-
-    let body = CreateFileRequestMetadata {
-        name:       folder_name,
-        mime_type:  "application/vnd.google-apps.folder",
-        id:         &id,
-        parents:    vec![parent]
-    };
-
-    let response = unwrap_req_err!(reqwest::blocking::Client::new().post("https://www.googleapis.com/drive/v3/files?supportsAllDrives=true")
-        .header("Content-Type","application/json")
-        .header("Authorization", &format!("Bearer {}", &access_token))
-        .body(serde_json::to_string(&body).unwrap())
-        .send());
-
-    let payload: GoogleResponse<()> = unwrap_req_err!(response.json());
-    unwrap_google_err!(payload);
-
-    Ok(id)
-}
-
-/// Upload a file to Google Drive and return it's ID
-///
-/// ## Params
-/// - `env` Env instance
-/// - `path` Path to the file to be uploaded
-/// - `parent` ID of the parent folder
-///
-/// ## Errors
-/// - Request failure
-/// - Error from Google API
-/// - Upon failing to identify MIME type
-/// - Upon failing to identify file name
-pub fn upload_file<P>(env: &Env, path: P, parent: &str) -> Result<String>
-where P: AsRef<Path> {
-    let access_token = get_access_token(env)?;
-    let id = get_id(env)?;
-    let file_name = match path.as_ref().file_name() {
-        Some(f) => f.to_str().unwrap(),
-        None => return Err((Error::Other("Missing file name".to_string()), line!(), file!()))
-    };
-
-    let mime = match mime_guess::from_path(&path).first() {
-        Some(g) => {
-            g.essence_str().to_string()
-        },
-        None => "application/octet-stream".to_string()
-    };
-
-    let body = CreateFileRequestMetadata {
-        name:       file_name,
-        parents:    vec![parent],
-        id:         &id,
-        mime_type:  &mime
-    };
-
-    let metadata_part = unwrap_req_err!(Part::text(serde_json::to_string(&body).unwrap()).mime_str("application/json"));
-    let file_part = unwrap_req_err!(unwrap_other_err!(Part::file(path)).mime_str(&mime));
-
-    let form = Form::new()
-        .part("Metadata", metadata_part)
-        .part("Media", file_part);
-
-    let response = unwrap_req_err!(reqwest::blocking::Client::new().post("https://www.googleapis.com/upload/drive/v3/files?uploadType=multipart&supportsAllDrives=true")
-        .multipart(form)
-        .header("Content-Type", "multipart/related")
-        .header("Authorization", &format!("Bearer {}", &access_token))
-        .send());
-
-    let payload: GoogleResponse<()> = unwrap_req_err!(response.json());
-    unwrap_google_err!(payload);
-
-    Ok(id)
-}
-
 /// Struct describing the request the the file list API
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -139,15 +84,26 @@ struct FileListRequest<'a> {
     /// Do we include items from all drives, no, we don't
     include_items_from_all_drives:  bool,
 
+    /// The maximum number of files to return per page
+    page_size:                      u32,
+
+    /// The token of the page to get, if not the first page
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page_token:                     Option<&'a str>,
+
     /// The fields to get
     fields:                         &'static str
 }
 
 /// Struct describing the response to a call to the list API
 #[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
 struct FileListResponse {
     /// The files returned
-    files:  Vec<File>
+    files:              Vec<File>,
+
+    /// The token of the next page, if there is one
+    next_page_token:    Option<String>
 }
 
 /// Struct describing an individual file returned by the list API
@@ -158,46 +114,48 @@ pub struct File {
     pub id:             String,
     /// The name of the file
     pub name:           String,
+    /// The MIME type of the file
+    pub mime_type:      String,
     /// The time the file was last modified
     pub modified_time:  String,
+    /// The MD5 checksum of the file's content. Only present for files with binary content in Google Drive
+    pub md5_checksum:   Option<String>,
+    /// The size of the file in bytes. Only present for files with binary content in Google Drive
+    #[serde(default, deserialize_with = "deserialize_optional_i64")]
+    pub size:           Option<i64>,
+    /// The IDs of the parent folders of the file. Only requested by the changes API
+    #[serde(default)]
+    pub parents:        Vec<String>
 }
 
-/// List the files in Google Drive
-///
-/// ## Params
-/// - `env` Env instance
-/// - `q` Search parameter, refer to [Google docs](https://developers.google.com/drive/api/v3/search-files)
-/// - `drive_id` If Team Drive, the ID of that Team Drive
-///
-/// ## Error
-/// - Request failure
-/// - Error from Google API
-pub fn list_files(env: &Env, q: Option<&str>, drive_id: Option<&str>) -> Result<Vec<File>> {
-    let query_params = FileListRequest {
-        q,
-        drive_id,
-        corpora:                        if drive_id.is_some() { "drive" } else { "user" },
-        supports_all_drives:            true,
-        include_items_from_all_drives:  true,
-        fields:                         "kind,incompleteSearch,files/kind,files/modifiedTime,files/id,files/name"
-    };
-
-    let access_token = get_access_token(env)?;
-    let req = unwrap_req_err!(reqwest::blocking::Client::new().get(format!("https://www.googleapis.com/drive/v3/files?{}", serde_qs::to_string(&query_params).unwrap()))
-        .header("Authorization", &format!("Bearer {}", &access_token))
-        .send());
-
-    let request_payload: GoogleResponse<FileListResponse> = unwrap_req_err!(req.json());
-    let payload = unwrap_google_err!(request_payload);
-
-    Ok(payload.files)
+/// Deserialize an optional 64-bit integer, which Google encodes as a String
+fn deserialize_optional_i64<'de, D>(deserializer: D) -> std::result::Result<Option<i64>, D::Error>
+where D: Deserializer<'de> {
+    let value: Option<String> = Option::deserialize(deserializer)?;
+    value.map(|value| value.parse().map_err(serde::de::Error::custom)).transpose()
+}
+
+/// Struct describing the query parameters used when listing shared drives
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SharedDriveRequest<'a> {
+    /// The maximum number of drives to return per page
+    page_size:  u32,
+
+    /// The token of the page to get, if not the first page
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page_token: Option<&'a str>
 }
 
 /// Struct describing the response to the shared drives API
 #[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
 struct SharedDriveResponse {
     /// The returned drives
-    drives: Vec<SharedDrive>,
+    drives:             Vec<SharedDrive>,
+
+    /// The token of the next page, if there is one
+    next_page_token:    Option<String>
 }
 
 /// Struct describing the individual drives returned by the shared shared drives API
@@ -209,22 +167,22 @@ pub struct SharedDrive {
     pub name:   String
 }
 
-/// Get all shared drives the user has access too
-///
-/// # Error
-/// - Google API error
-/// - Reqwest error
-pub fn get_shared_drives(env: &Env) -> Result<Vec<SharedDrive>> {
-    let access_token = get_access_token(env)?;
-
-    let request = unwrap_req_err!(reqwest::blocking::Client::new().get("https://www.googleapis.com/drive/v3/drives?pageSize=100")
-        .header("Authorization", &format!("Bearer {}", &access_token))
-        .send());
+/// Struct describing the response to a call to the about API
+#[derive(Deserialize, Debug)]
+pub struct About {
+    /// The user GSync is authenticated as
+    pub user:   User
+}
 
-    let response: GoogleResponse<SharedDriveResponse> = unwrap_req_err!(request.json());
-    let payload = unwrap_google_err!(response);
+/// Struct describing a Google account
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct User {
+    /// The name of the account
+    pub display_name:   String,
 
-    Ok(payload.drives)
+    /// The email address of the account
+    pub email_address:  Option<String>
 }
 
 /// Struct describing the response to a call to the generateIds API
@@ -234,46 +192,6 @@ struct GetIdsResponse {
     ids:    Vec<String>
 }
 
-/// Get a File ID from the IDS Vec. If this Vec contains no more IDs, a new set will be requested from Google.
-///
-/// ## Params
-/// - `env` Env instance
-///
-/// ## Errors
-/// - Request failure
-/// - Error from Google API
-fn get_id(env: &Env) -> Result<String> {
-    let mut lock = unwrap_other_err!(IDS.lock());
-    let vec = lock.get_mut();
-
-    let access_token = get_access_token(env)?;
-
-    if vec.is_empty() {
-        let mut new_ids = get_ids_from_google(&access_token)?;
-        let id = new_ids.pop().unwrap();
-        lock.set(new_ids);
-
-        return Ok(id);
-    }
-
-    Ok(vec.pop().unwrap())
-}
-
-/// Request 100 new File IDs from Google. Do not call this function directly, instead use `get_id()`
-///
-/// ## Errors
-/// - Request failure
-/// - Error from Google API
-fn get_ids_from_google(access_token: &str) -> Result<Vec<String>> {
-    let request = unwrap_req_err!(reqwest::blocking::Client::new().get("https://www.googleapis.com/drive/v3/files/generateIds?count=100")
-        .header("Authorization", &format!("Bearer {}", access_token))
-        .send());
-
-    let payload: GoogleResponse<GetIdsResponse> = unwrap_req_err!(request.json());
-    let ids = unwrap_google_err!(payload);
-    Ok(ids.ids)
-}
-
 /// Struct describing the query parameters used when updating a file
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -292,74 +210,564 @@ struct UpdateFileRequest<'a> {
     mime_type: &'a str
 }
 
-/// Update a file in Google Drive. The caller should make sure the file exists.
-///
-/// ## Params
-/// - `env` Env instance
-/// - `path` Path to the file to be updated
-/// - `id` The ID of the existing file in Google Drive to be updated
-///
-/// ## Errors
-/// - Request failure
-/// - Google API error
-/// - Failure to construct multipart parts
-pub fn update_file<P>(env: &Env, path: P, id: &str) -> Result<()>
-where P: AsRef<Path> {
-    let access_token = get_access_token(env)?;
-    let query = UpdateFileRequestQuery {
-        supports_all_drives:    true,
-        upload_type:            "multipart"
-    };
-
-    let mime = match mime_guess::from_path(&path).first() {
-        Some(g) => {
-            g.essence_str().to_string()
-        },
-        None => "application/octet-stream".to_string()
-    };
-
-    let payload = UpdateFileRequest {
-        mime_type: &mime
-    };
-
-    let metadata_part = unwrap_req_err!(Part::text(unwrap_other_err!(serde_json::to_string(&payload))).mime_str("application/json"));
-    let file_part = unwrap_req_err!(unwrap_other_err!(Part::file(&path)).mime_str(&mime));
-
-    let form = Form::new()
-        .part("Metadata", metadata_part)
-        .part("Media", file_part);
+/// Struct describing the metadata used when moving a file to the trash
+#[derive(Serialize)]
+struct TrashFileRequest {
+    /// Whether the file should be trashed
+    trashed:    bool
+}
+
+/// Struct describing the query parameters used when getting the start page token of the changes API
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StartPageTokenRequest<'a> {
+    /// The ID of the shared drive to track changes in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    drive_id:               Option<&'a str>,
+
+    /// If we support all drives, we do
+    supports_all_drives:    bool
+}
+
+/// Struct describing the response to a call to the getStartPageToken API
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct StartPageTokenResponse {
+    /// The token from which future changes are listed
+    start_page_token:   String
+}
+
+/// Struct describing the query parameters used when listing changes
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ChangeListRequest<'a> {
+    /// The token of the page to list
+    page_token:                     &'a str,
+
+    /// The ID of the shared drive to list changes in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    drive_id:                       Option<&'a str>,
+
+    /// If we support all drives, we do
+    supports_all_drives:            bool,
+
+    /// Whether to include changes in shared drives
+    include_items_from_all_drives:  bool,
+
+    /// Whether to include changes indicating a file was removed
+    include_removed:                bool,
 
-    let uri = format!("https://www.googleapis.com/upload/drive/v3/files/{}?{}", id, unwrap_other_err!(serde_qs::to_string(&query)));
-    let response = unwrap_req_err!(reqwest::blocking::Client::new().patch(&uri)
-        .multipart(form)
-        .header("Content-Type", "multipart/related")
-        .header("Authorization", &format!("Bearer {}", access_token))
-        .send());
+    /// The maximum number of changes to return per page
+    page_size:                      u32,
 
-    let payload: GoogleResponse<()> = unwrap_req_err!(response.json());
-    unwrap_google_err!(payload);
+    /// The fields to get
+    fields:                         &'static str
+}
 
-    Ok(())
+/// Struct describing the response to a call to the changes list API
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ChangeListResponse {
+    /// The changes on this page
+    changes:                Vec<Change>,
+
+    /// The token of the next page, if there is one
+    next_page_token:        Option<String>,
+
+    /// The token for future changes. Only present on the last page
+    new_start_page_token:   Option<String>
 }
 
-/// Permanently delete a file
-///
-/// ## Params
-/// - `env` Env instance
-/// - `id` The ID of the existing file in Google Drive to be updated
+/// Struct describing a single change to a file
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Change {
+    /// The ID of the file which changed
+    pub file_id:    String,
+    /// Whether the file was removed, or is no longer accessible
+    #[serde(default)]
+    pub removed:    bool,
+    /// The file as it is now. Absent when the file was removed
+    pub file:       Option<File>
+}
+
+impl DriveClient {
+    /// Create a client for the Google Drive API, authorized with the tokens of the logged in user
+    pub fn new(env: &Env) -> Self {
+        let http = reqwest::Client::new();
+        Self {
+            tokens:     Arc::new(TokenProvider::new(env, http.clone())),
+            http,
+            base_url:   api::base_url(GOOGLE_APIS_URL_VAR, GOOGLE_APIS_URL),
+            env:        env.clone(),
+            ids:        Arc::new(Mutex::new(Vec::new()))
+        }
+    }
+
+    /// Get a client for another Env. The connection pool and the access token are shared.
+    /// Sessions of interrupted uploads are stored by root folder, so a client has to be created again once the root folder is known
+    pub fn with_env(&self, env: &Env) -> Self {
+        Self {
+            env: env.clone(),
+            ..self.clone()
+        }
+    }
+
+    /// The HTTP client requests are sent with
+    pub fn http(&self) -> &reqwest::Client {
+        &self.http
+    }
+
+    /// The Env instance this client was created with
+    pub fn env(&self) -> &Env {
+        &self.env
+    }
+
+    /// Get an access token to authorize a request with
+    ///
+    /// ## Errors
+    /// - When a database error occurs
+    /// - When refreshing the access token fails
+    pub async fn access_token(&self) -> Result<String> {
+        self.tokens.access_token().await
+    }
+
+    /// Send a request authorized with the user's access token. Requests which are rate limited or fail temporarily are retried,
+    /// as configured in the Env. `request` builds the request for every attempt from the access token
+    ///
+    /// ## Errors
+    /// - When getting an access token fails
+    /// - When building the request fails
+    /// - Request failure
+    /// - Google API error which isn't transient, or which persisted for every attempt
+    pub async fn send<F>(&self, request: F) -> Result<reqwest::Response>
+    where F: Fn(&str) -> Result<reqwest::RequestBuilder> {
+        let request = &request;
+        retry::send(self.env.max_attempts, move || async move {
+            let access_token = self.access_token().await?;
+            request(&access_token)
+        }).await
+    }
+
+    /// Create a folder in Google Drive, and return it's ID
+    ///
+    /// ## Params
+    /// - `folder_name` The name of the folder to create
+    /// - `parent` ID of parent folder
+    ///
+    /// ## Errors
+    /// - Request failure
+    /// - Google API error
+    pub async fn create_folder(&self, folder_name: &str, parent: &str) -> Result<String> {
+        let id = self.get_id().await?;
+
+        let body = CreateFileRequestMetadata {
+            name:       folder_name,
+            mime_type:  FOLDER_MIME_TYPE,
+            id:         &id,
+            parents:    vec![parent]
+        };
+
+        let response = self.send(|access_token| Ok(self.http.post(format!("{}/drive/v3/files?supportsAllDrives=true", self.base_url))
+            .header("Content-Type","application/json")
+            .header("Authorization", &format!("Bearer {}", &access_token))
+            .body(serde_json::to_string(&body).unwrap()))).await?;
+
+        let payload: GoogleResponse<()> = unwrap_req_err!(response.json().await);
+        unwrap_google_err!(payload);
+
+        Ok(id)
+    }
+
+    /// Upload a file to Google Drive and return it's ID. Large files are uploaded with a resumable upload
+    ///
+    /// ## Params
+    /// - `path` Path to the file to be uploaded
+    /// - `parent` ID of the parent folder
+    ///
+    /// ## Errors
+    /// - Request failure
+    /// - Error from Google API
+    /// - Upon failing to identify MIME type
+    /// - Upon failing to identify file name
+    pub async fn upload_file<P>(&self, path: P, parent: &str) -> Result<String>
+    where P: AsRef<Path> {
+        let id = self.get_id().await?;
+        let file_name = match path.as_ref().file_name() {
+            Some(f) => f.to_str().unwrap(),
+            None => return Err(new_err!(ErrorKind::Other("Missing file name".to_string())))
+        };
+
+        let mime = match mime_guess::from_path(&path).first() {
+            Some(g) => {
+                g.essence_str().to_string()
+            },
+            None => "application/octet-stream".to_string()
+        };
+
+        let body = CreateFileRequestMetadata {
+            name:       file_name,
+            parents:    vec![parent],
+            id:         &id,
+            mime_type:  &mime
+        };
+
+        if unwrap_io_err!(std::fs::metadata(&path)).len() >= RESUMABLE_THRESHOLD {
+            return resumable::upload(self, path.as_ref(), UploadRequest {
+                method:     reqwest::Method::POST,
+                uri:        format!("{}/upload/drive/v3/files?uploadType=resumable&supportsAllDrives=true", self.base_url),
+                metadata:   unwrap_other_err!(serde_json::to_string(&body)),
+                mime_type:  &mime,
+                file_id:    &id,
+                target:     parent
+            }).await;
+        }
+
+        let contents = unwrap_io_err!(tokio::fs::read(&path).await);
+        let metadata = serde_json::to_string(&body).unwrap();
+
+        let response = self.send(|access_token| Ok(self.http.post(format!("{}/upload/drive/v3/files?uploadType=multipart&supportsAllDrives=true", self.base_url))
+            .multipart(multipart_form(&metadata, &contents, file_name, &mime)?)
+            .header("Content-Type", "multipart/related")
+            .header("Authorization", &format!("Bearer {}", &access_token)))).await?;
+
+        let payload: GoogleResponse<()> = unwrap_req_err!(response.json().await);
+        unwrap_google_err!(payload);
+
+        Ok(id)
+    }
+
+    /// List the files in Google Drive. All pages are requested, with the page size configured in the Env
+    ///
+    /// ## Params
+    /// - `q` Search parameter, refer to [Google docs](https://developers.google.com/drive/api/v3/search-files)
+    /// - `drive_id` If Team Drive, the ID of that Team Drive
+    ///
+    /// ## Error
+    /// - Request failure
+    /// - Error from Google API
+    pub async fn list_files(&self, q: Option<&str>, drive_id: Option<&str>) -> Result<Vec<File>> {
+
+        let mut files = Vec::new();
+        let mut page_token = None;
+        loop {
+            let query_params = FileListRequest {
+                q,
+                drive_id,
+                corpora:                        if drive_id.is_some() { "drive" } else { "user" },
+                supports_all_drives:            true,
+                include_items_from_all_drives:  true,
+                page_size:                      self.env.page_size.unwrap_or(MAX_FILES_PAGE_SIZE).min(MAX_FILES_PAGE_SIZE),
+                page_token:                     page_token.as_deref(),
+                fields:                         "kind,incompleteSearch,nextPageToken,files/kind,files/modifiedTime,files/id,files/name,files/mimeType,files/md5Checksum,files/size"
+            };
+
+            let req = self.send(|access_token| Ok(self.http.get(format!("{}/drive/v3/files?{}", self.base_url, serde_qs::to_string(&query_params).unwrap()))
+                .header("Authorization", &format!("Bearer {}", &access_token)))).await?;
+
+            let request_payload: GoogleResponse<FileListResponse> = unwrap_req_err!(req.json().await);
+            let mut payload = unwrap_google_err!(request_payload);
+            files.append(&mut payload.files);
+
+            match payload.next_page_token {
+                Some(next_page_token) => page_token = Some(next_page_token),
+                None => return Ok(files)
+            }
+        }
+    }
+
+    /// Get all shared drives the user has access too. All pages are requested, with the page size configured in the Env
+    ///
+    /// # Error
+    /// - Google API error
+    /// - Reqwest error
+    pub async fn get_shared_drives(&self) -> Result<Vec<SharedDrive>> {
+
+        let mut drives = Vec::new();
+        let mut page_token = None;
+        loop {
+            let query = SharedDriveRequest {
+                page_size:  self.env.page_size.unwrap_or(MAX_DRIVES_PAGE_SIZE).min(MAX_DRIVES_PAGE_SIZE),
+                page_token: page_token.as_deref()
+            };
+
+            let uri = format!("{}/drive/v3/drives?{}", self.base_url, unwrap_other_err!(serde_qs::to_string(&query)));
+            let request = self.send(|access_token| Ok(self.http.get(&uri)
+                .header("Authorization", &format!("Bearer {}", &access_token)))).await?;
+
+            let response: GoogleResponse<SharedDriveResponse> = unwrap_req_err!(request.json().await);
+            let mut payload = unwrap_google_err!(response);
+            drives.append(&mut payload.drives);
+
+            match payload.next_page_token {
+                Some(next_page_token) => page_token = Some(next_page_token),
+                None => return Ok(drives)
+            }
+        }
+    }
+
+    /// Get the account GSync is authenticated as
+    ///
+    /// ## Errors
+    /// - Request failure
+    /// - Google API error
+    pub async fn about(&self) -> Result<About> {
+        let uri = format!("{}/drive/v3/about?fields=user(displayName,emailAddress)", self.base_url);
+        let response = self.send(|access_token| Ok(self.http.get(&uri)
+            .header("Authorization", &format!("Bearer {}", access_token)))).await?;
+
+        let payload: GoogleResponse<About> = unwrap_req_err!(response.json().await);
+        Ok(unwrap_google_err!(payload))
+    }
+
+    /// Get a File ID from the pool of IDs. If the pool contains no more IDs, a new set will be requested from Google.
+    ///
+    /// ## Errors
+    /// - Request failure
+    /// - Error from Google API
+    async fn get_id(&self) -> Result<String> {
+        let mut ids = self.ids.lock().await;
+        if ids.is_empty() {
+            *ids = self.get_ids_from_google().await?;
+        }
+
+        match ids.pop() {
+            Some(id) => Ok(id),
+            None => Err(new_err!(ErrorKind::Other("Google returned no file IDs".to_string())))
+        }
+    }
+
+    /// Request 100 new File IDs from Google. Do not call this function directly, instead use `get_id()`
+    ///
+    /// ## Errors
+    /// - Request failure
+    /// - Error from Google API
+    async fn get_ids_from_google(&self) -> Result<Vec<String>> {
+        let request = self.send(|access_token| Ok(self.http.get(format!("{}/drive/v3/files/generateIds?count=100", self.base_url))
+            .header("Authorization", &format!("Bearer {}", access_token)))).await?;
+
+        let payload: GoogleResponse<GetIdsResponse> = unwrap_req_err!(request.json().await);
+        let ids = unwrap_google_err!(payload);
+        Ok(ids.ids)
+    }
+
+    /// Update a file in Google Drive. The caller should make sure the file exists. Large files are uploaded with a resumable upload
+    ///
+    /// ## Params
+    /// - `path` Path to the file to be updated
+    /// - `id` The ID of the existing file in Google Drive to be updated
+    ///
+    /// ## Errors
+    /// - Request failure
+    /// - Google API error
+    /// - Failure to construct multipart parts
+    pub async fn update_file<P>(&self, path: P, id: &str) -> Result<()>
+    where P: AsRef<Path> {
+        let query = UpdateFileRequestQuery {
+            supports_all_drives:    true,
+            upload_type:            "multipart"
+        };
+
+        let mime = match mime_guess::from_path(&path).first() {
+            Some(g) => {
+                g.essence_str().to_string()
+            },
+            None => "application/octet-stream".to_string()
+        };
+
+        let payload = UpdateFileRequest {
+            mime_type: &mime
+        };
+
+        if unwrap_io_err!(std::fs::metadata(&path)).len() >= RESUMABLE_THRESHOLD {
+            let query = UpdateFileRequestQuery {
+                supports_all_drives:    true,
+                upload_type:            "resumable"
+            };
+
+            resumable::upload(self, path.as_ref(), UploadRequest {
+                method:     reqwest::Method::PATCH,
+                uri:        format!("{}/upload/drive/v3/files/{}?{}", self.base_url, id, unwrap_other_err!(serde_qs::to_string(&query))),
+                metadata:   unwrap_other_err!(serde_json::to_string(&payload)),
+                mime_type:  &mime,
+                file_id:    id,
+                target:     id
+            }).await?;
+
+            return Ok(());
+        }
+
+        let file_name = path.as_ref().file_name().map(|f| f.to_str().unwrap().to_string()).unwrap_or_default();
+        let contents = unwrap_io_err!(tokio::fs::read(&path).await);
+        let metadata = unwrap_other_err!(serde_json::to_string(&payload));
+
+        let uri = format!("{}/upload/drive/v3/files/{}?{}", self.base_url, id, unwrap_other_err!(serde_qs::to_string(&query)));
+        let response = self.send(|access_token| Ok(self.http.patch(&uri)
+            .multipart(multipart_form(&metadata, &contents, &file_name, &mime)?)
+            .header("Content-Type", "multipart/related")
+            .header("Authorization", &format!("Bearer {}", access_token)))).await?;
+
+        let payload: GoogleResponse<()> = unwrap_req_err!(response.json().await);
+        unwrap_google_err!(payload);
+
+        Ok(())
+    }
+
+    /// Download the contents of a file to a local path, overwriting the local file if it exists.
+    /// The contents are written to a temporary file first, which replaces the local file once the download is complete.
+    /// Only works for files with binary content, not for e.g. Google Docs
+    ///
+    /// ## Params
+    /// - `id` The ID of the file in Google Drive to download
+    /// - `path` The local path to write the contents to
+    ///
+    /// ## Errors
+    /// - Request failure
+    /// - Google API error
+    /// - When writing the local file fails
+    pub async fn download_file<P>(&self, id: &str, path: P) -> Result<()>
+    where P: AsRef<Path> {
+        let uri = format!("{}/drive/v3/files/{}?alt=media&supportsAllDrives=true", self.base_url, id);
+        let mut response = self.send(|access_token| Ok(self.http.get(&uri)
+            .header("Authorization", &format!("Bearer {}", access_token)))).await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let payload: GoogleResponse<()> = unwrap_req_err!(response.json().await);
+            unwrap_google_err!(payload);
+            return Err(new_err!(ErrorKind::Other(format!("Downloading file '{}' failed with status {}", id, status))));
+        }
+
+        backend::download_atomically(path.as_ref(), |partial| async move {
+            let mut file = unwrap_io_err!(tokio::fs::File::create(partial).await);
+            while let Some(chunk) = unwrap_req_err!(response.chunk().await) {
+                unwrap_io_err!(file.write_all(&chunk).await);
+            }
+
+            unwrap_io_err!(file.flush().await);
+            Ok(())
+        }).await
+    }
+
+    /// Permanently delete a file
+    ///
+    /// ## Params
+    /// - `id` The ID of the existing file in Google Drive to be updated
+    ///
+    /// ## Errors
+    /// - Request failure
+    /// - Google API error
+    pub async fn delete_file(&self, id: &str) -> Result<()> {
+        let uri = format!("{}/drive/v3/files/{}?supportsAllDrives=true", self.base_url, id);
+        let response = self.send(|access_token| Ok(self.http.delete(&uri)
+            .header("Authorization", &format!("Bearer {}", access_token)))).await?;
+
+        // Google responds with 204 No Content, without a body, when the file was deleted
+        let status = response.status();
+        if !status.is_success() {
+            let payload: GoogleResponse<()> = unwrap_req_err!(response.json().await);
+            unwrap_google_err!(payload);
+            return Err(new_err!(ErrorKind::Status(status)));
+        }
+
+        Ok(())
+    }
+
+    /// Move a file to the trash
+    ///
+    /// ## Params
+    /// - `id` The ID of the existing file in Google Drive to be trashed
+    ///
+    /// ## Errors
+    /// - Request failure
+    /// - Google API error
+    pub async fn trash_file(&self, id: &str) -> Result<()> {
+        let uri = format!("{}/drive/v3/files/{}?supportsAllDrives=true", self.base_url, id);
+        let response = self.send(|access_token| Ok(self.http.patch(&uri)
+            .header("Content-Type", "application/json")
+            .header("Authorization", &format!("Bearer {}", access_token))
+            .body(serde_json::to_string(&TrashFileRequest { trashed: true }).unwrap()))).await?;
+
+        let payload: GoogleResponse<()> = unwrap_req_err!(response.json().await);
+        unwrap_google_err!(payload);
+
+        Ok(())
+    }
+
+    /// Get the page token from which changes made after this moment can be listed with `list_changes`
+    ///
+    /// ## Params
+    /// - `drive_id` If Team Drive, the ID of that Team Drive
+    ///
+    /// ## Errors
+    /// - Request failure
+    /// - Google API error
+    pub async fn get_start_page_token(&self, drive_id: Option<&str>) -> Result<String> {
+        let query = StartPageTokenRequest {
+            drive_id,
+            supports_all_drives:    true
+        };
+
+        let uri = format!("{}/drive/v3/changes/startPageToken?{}", self.base_url, unwrap_other_err!(serde_qs::to_string(&query)));
+        let response = self.send(|access_token| Ok(self.http.get(&uri)
+            .header("Authorization", &format!("Bearer {}", access_token)))).await?;
+
+        let payload: GoogleResponse<StartPageTokenResponse> = unwrap_req_err!(response.json().await);
+        let payload = unwrap_google_err!(payload);
+
+        Ok(payload.start_page_token)
+    }
+
+    /// List all changes made since the page token was obtained. Returns the changes and the page token from which future changes can be listed
+    ///
+    /// ## Params
+    /// - `page_token` A token from `get_start_page_token`, or returned by a previous call to this function
+    /// - `drive_id` If Team Drive, the ID of that Team Drive
+    ///
+    /// ## Errors
+    /// - Request failure
+    /// - Google API error
+    pub async fn list_changes(&self, page_token: &str, drive_id: Option<&str>) -> Result<(Vec<Change>, String)> {
+
+        let mut changes = Vec::new();
+        let mut page_token = page_token.to_string();
+        loop {
+            let query = ChangeListRequest {
+                page_token:                     &page_token,
+                drive_id,
+                supports_all_drives:            true,
+                include_items_from_all_drives:  true,
+                include_removed:                true,
+                page_size:                      self.env.page_size.unwrap_or(MAX_FILES_PAGE_SIZE).min(MAX_FILES_PAGE_SIZE),
+                fields:                         "nextPageToken,newStartPageToken,changes(fileId,removed,file(id,name,mimeType,modifiedTime,md5Checksum,size,parents))"
+            };
+
+            let uri = format!("{}/drive/v3/changes?{}", self.base_url, unwrap_other_err!(serde_qs::to_string(&query)));
+            let response = self.send(|access_token| Ok(self.http.get(&uri)
+                .header("Authorization", &format!("Bearer {}", access_token)))).await?;
+
+            let payload: GoogleResponse<ChangeListResponse> = unwrap_req_err!(response.json().await);
+            let mut payload = unwrap_google_err!(payload);
+            changes.append(&mut payload.changes);
+
+            match (payload.next_page_token, payload.new_start_page_token) {
+                (Some(next_page_token), _) => page_token = next_page_token,
+                (None, Some(new_start_page_token)) => return Ok((changes, new_start_page_token)),
+                (None, None) => return Err(new_err!(ErrorKind::Other("Google returned neither a next page token nor a new start page token".to_string())))
+            }
+        }
+    }
+}
+
+/// Create the multipart body of an upload, consisting of the file's metadata as JSON and its contents
 ///
 /// ## Errors
-/// - Request failure
-/// - Google API error
-pub fn delete_file(env: &Env, id: &str) -> Result<()> {
-    let access_token = get_access_token(env)?;
-    let uri = format!("https://www.googleapis.com/drive/v3/files/{}?supportsAllDrives=true", id);
-    let response = unwrap_req_err!(reqwest::blocking::Client::new().delete(&uri)
-        .header("Authorization", &format!("Bearer {}", access_token))
-        .send());
-
-    let payload: GoogleResponse<()> = unwrap_req_err!(response.json());
-    unwrap_google_err!(payload);
-
-    Ok(())
-}
\ No newline at end of file
+/// - When `mime` is not a valid MIME type
+fn multipart_form(metadata: &str, contents: &[u8], file_name: &str, mime: &str) -> Result<Form> {
+    let metadata_part = unwrap_req_err!(Part::text(metadata.to_string()).mime_str("application/json"));
+    let file_part = unwrap_req_err!(Part::bytes(contents.to_vec()).file_name(file_name.to_string()).mime_str(mime));
+
+    Ok(Form::new()
+        .part("Metadata", metadata_part)
+        .part("Media", file_part))
+}
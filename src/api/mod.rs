@@ -2,8 +2,20 @@
 
 pub mod drive;
 pub mod oauth;
+pub mod resumable;
+pub mod retry;
 
 use serde::Deserialize;
+use std::fmt;
+
+/// Get the base URL of a Google API, without a trailing slash. The environment variable `var` overrides `default`,
+/// e.g. to send the requests to a fake server in tests
+pub fn base_url(var: &str, default: &str) -> String {
+    match std::env::var(var) {
+        Ok(url) if !url.is_empty() => url.trim_end_matches('/').to_string(),
+        _ => default.to_string()
+    }
+}
 
 /// Struct describing a generic response from a Google API
 #[derive(Deserialize, Debug)]
@@ -29,6 +41,19 @@ pub struct GoogleError {
     pub errors:     Vec<ErrorData>
 }
 
+impl fmt::Display for GoogleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.code, self.message)?;
+        if let Some(data) = self.errors.first() {
+            write!(f, " ({})", data.reason)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for GoogleError {}
+
 /// Struct describing a specific Error returned from a Google API
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -3,8 +3,35 @@
 use crate::env::Env;
 use serde::{Deserialize, Serialize};
 
-use crate::{Result, unwrap_req_err, unwrap_db_err, unwrap_google_err};
-use crate::api::GoogleResponse;
+use crate::{Result, ErrorKind, unwrap_req_err, unwrap_db_err, unwrap_google_err, unwrap_other_err, unwrap_io_err, new_err};
+use crate::error::ResultExt;
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use crate::api::{self, GoogleResponse};
+use crate::api::retry;
+use tokio::sync::Mutex;
+
+/// The base URL of Google's OAuth2 API
+const OAUTH_URL: &str = "https://oauth2.googleapis.com";
+
+/// The environment variable overriding `OAUTH_URL`
+const OAUTH_URL_VAR: &str = "GSYNC_OAUTH_URL";
+
+/// The scope requested in the device flow. Google doesn't allow the full Drive scope there, only access to the files GSync created itself
+const DEVICE_SCOPE: &str = "https://www.googleapis.com/auth/drive.file";
+
+/// The grant type of the token requests in the device flow
+const DEVICE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// The scope requested for a service account
+const SERVICE_ACCOUNT_SCOPE: &str = "https://www.googleapis.com/auth/drive";
+
+/// The grant type of the token requests of a service account
+const JWT_BEARER_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:jwt-bearer";
+
+/// Seconds until the JWT assertion of a service account expires. Google allows at most an hour
+const ASSERTION_LIFETIME: i64 = 3600;
 
 /// Login Data
 pub struct LoginData {
@@ -105,6 +132,127 @@ struct RefreshTokenResponse {
     expires_in:     i64,
 }
 
+/// The URL of Google's OAuth2 token endpoint
+fn token_url() -> String {
+    format!("{}/token", api::base_url(OAUTH_URL_VAR, OAUTH_URL))
+}
+
+/// Struct describing the request for a device code
+#[derive(Serialize)]
+struct DeviceCodeRequest<'a> {
+    /// Application's client ID
+    client_id:  &'a str,
+
+    /// The scopes requested
+    scope:      &'static str
+}
+
+/// Struct describing the code the user enters on another device to log in, as returned by Google
+#[derive(Deserialize)]
+pub struct DeviceCode {
+    /// The code identifying this login attempt when polling for tokens
+    pub device_code:        String,
+
+    /// The code the user enters at `verification_url`
+    pub user_code:          String,
+
+    /// The URL the user enters `user_code` at
+    pub verification_url:   String,
+
+    /// Seconds until the codes expire
+    pub expires_in:         i64,
+
+    /// Seconds to wait between polling for tokens
+    pub interval:           u64
+}
+
+/// Struct describing the request to poll for the tokens in the device flow
+#[derive(Serialize)]
+struct DeviceTokenRequest<'a> {
+    /// Application's client ID
+    client_id:      &'a str,
+
+    /// Application's client secret
+    client_secret:  &'a str,
+
+    /// The device code
+    device_code:    &'a str,
+
+    /// The type of grant
+    grant_type:     &'static str
+}
+
+/// Struct describing an error returned by the OAuth2 endpoints, which don't use the error format of the other Google APIs
+#[derive(Deserialize)]
+struct OAuthError {
+    /// The error code, e.g. `authorization_pending`
+    error:              String,
+
+    /// A description of the error
+    error_description:  Option<String>
+}
+
+/// Struct describing the JSON key of a service account, as downloaded from the Google Cloud console. Only the fields GSync uses
+#[derive(Deserialize)]
+struct ServiceAccountKey {
+    /// The email address identifying the service account
+    client_email:   String,
+
+    /// The PEM encoded private key of the service account
+    private_key:    String
+}
+
+/// Struct describing the header of a JWT
+#[derive(Serialize)]
+struct JwtHeader {
+    /// The signing algorithm
+    alg:    &'static str,
+
+    /// The type of the token
+    typ:    &'static str
+}
+
+/// Struct describing the claims of the JWT assertion of a service account
+#[derive(Serialize)]
+struct JwtClaims<'a> {
+    /// The email address of the service account
+    iss:    &'a str,
+
+    /// The scopes requested
+    scope:  &'static str,
+
+    /// The token endpoint the assertion is meant for
+    aud:    &'a str,
+
+    /// The moment the assertion was issued, in seconds since the epoch
+    iat:    i64,
+
+    /// The moment the assertion expires, in seconds since the epoch
+    exp:    i64
+}
+
+/// Struct describing the request to exchange the JWT assertion of a service account for an access token
+#[derive(Serialize)]
+struct JwtBearerRequest<'a> {
+    /// The type of grant
+    grant_type: &'static str,
+
+    /// The signed JWT
+    assertion:  &'a str
+}
+
+/// Enum describing the result of polling for the tokens in the device flow
+pub enum DevicePoll {
+    /// The user hasn't entered the code yet
+    Pending,
+
+    /// Polling too often, the interval should be increased
+    SlowDown,
+
+    /// The user logged in
+    Done(LoginData)
+}
+
 /// Create an authentication URL used for step 1 in the OAuth2 flow
 pub fn create_authentication_uri(env: &Env, code_challenge: &str, state: &str, redirect_uri: &str) -> String {
     let auth_request = AuthenticationRequest {
@@ -112,9 +260,9 @@ pub fn create_authentication_uri(env: &Env, code_challenge: &str, state: &str, r
         redirect_uri,
         response_type:          "code",
         scope:                  "https://www.googleapis.com/auth/drive",
-        code_challenge:         &code_challenge,
+        code_challenge,
         code_challenge_method:  "S256",
-        state:                  &state
+        state
     };
 
     let qstring = serde_qs::to_string(&auth_request).unwrap();
@@ -127,7 +275,7 @@ pub fn create_authentication_uri(env: &Env, code_challenge: &str, state: &str, r
 /// ## Errors
 /// - Google API error
 /// - Reqwest error
-pub fn exchange_access_token(env: &Env, access_token: &str, code_verifier: &str, redirect_uri: &str) -> Result<LoginData> {
+pub async fn exchange_access_token(env: &Env, http: &reqwest::Client, access_token: &str, code_verifier: &str, redirect_uri: &str) -> Result<LoginData> {
 
     //We can now exchange this token for a refresh_token and the likes
     let exchange_request = ExchangeAccessTokenRequest {
@@ -140,12 +288,12 @@ pub fn exchange_access_token(env: &Env, access_token: &str, code_verifier: &str,
     };
 
     // Send a request to Google to exchange the code for the necessary codes
-    let response = unwrap_req_err!(reqwest::blocking::Client::new().post("https://oauth2.googleapis.com/token")
-        .body(serde_json::to_string(&exchange_request).unwrap())
-        .send());
+    let body = serde_json::to_string(&exchange_request).unwrap();
+    let token_url = token_url();
+    let response = retry::send(env.max_attempts, || async { Ok(http.post(&token_url).body(body.clone())) }).await?;
 
     // Deserialize from JSON
-    let exchange_response: GoogleResponse<ExchangeAccessTokenResponse> = unwrap_req_err!(response.json());
+    let exchange_response: GoogleResponse<ExchangeAccessTokenResponse> = unwrap_req_err!(response.json().await);
     let token_response = unwrap_google_err!(exchange_response);
 
     Ok(LoginData {
@@ -155,39 +303,249 @@ pub fn exchange_access_token(env: &Env, access_token: &str, code_verifier: &str,
     })
 }
 
-/// Get an access token
+/// Request a code for the device flow, which the user enters on another device to log in
 ///
 /// ## Errors
-/// - When a database error occurs
-/// - When the Google API returns an error
-/// - When reqwest returns an error
-pub fn get_access_token(env: &Env) -> Result<String> {
-    let conn = unwrap_db_err!(env.get_conn());
-    let mut stmt = unwrap_db_err!(conn.prepare("SELECT access_token, refresh_token, expiry FROM user"));
-    let mut result = unwrap_db_err!(stmt.query(rusqlite::named_params! {}));
+/// - When Google refuses the request
+/// - Reqwest error
+pub async fn request_device_code(env: &Env, http: &reqwest::Client) -> Result<DeviceCode> {
+    let request = DeviceCodeRequest {
+        client_id:  &env.client_id,
+        scope:      DEVICE_SCOPE
+    };
 
-    if let Ok(Some(row)) = result.next() {
-        let access_token = unwrap_db_err!(row.get::<&str, String>("access_token"));
-        let refresh_token = unwrap_db_err!(row.get::<&str, String>("refresh_token"));
-        let expiry = unwrap_db_err!(row.get::<&str, i64>("expiry"));
+    let body = serde_json::to_string(&request).unwrap();
+    let uri = format!("{}/device/code", api::base_url(OAUTH_URL_VAR, OAUTH_URL));
+    let response = retry::send(env.max_attempts, || async { Ok(http.post(&uri).body(body.clone())) }).await?;
+
+    if !response.status().is_success() {
+        let error: OAuthError = unwrap_req_err!(response.json().await);
+        if error.error == "invalid_client" {
+            return Err(new_err!(ErrorKind::Other("Google refused the client for logging in with a code. This requires OAuth2 credentials of type 'TVs and Limited Input devices', create those and configure them with `gsync config -i <ID> -s <SECRET>`".to_string())));
+        }
+
+        return Err(oauth_error(error));
+    }
+
+    Ok(unwrap_req_err!(response.json().await))
+}
+
+/// Poll for the tokens in the device flow, which Google hands out once the user entered the code
+///
+/// ## Errors
+/// - When the user denied access, or the code expired
+/// - Reqwest error
+pub async fn poll_device_token(env: &Env, http: &reqwest::Client, device_code: &str) -> Result<DevicePoll> {
+    let request = DeviceTokenRequest {
+        client_id:      &env.client_id,
+        client_secret:  &env.client_secret,
+        device_code,
+        grant_type:     DEVICE_GRANT_TYPE
+    };
+
+    // Not sent through `retry::send`, since waiting for the user is signalled with error responses, and polling repeats the request anyway
+    let body = unwrap_other_err!(serde_json::to_string(&request));
+    let response = unwrap_req_err!(http.post(token_url()).body(body).send().await);
+
+    if !response.status().is_success() {
+        let error: OAuthError = unwrap_req_err!(response.json().await);
+        return match error.error.as_str() {
+            "authorization_pending" => Ok(DevicePoll::Pending),
+            "slow_down" => Ok(DevicePoll::SlowDown),
+            _ => Err(oauth_error(error))
+        };
+    }
+
+    let token_response: ExchangeAccessTokenResponse = unwrap_req_err!(response.json().await);
+    Ok(DevicePoll::Done(LoginData {
+        access_token:   token_response.access_token,
+        refresh_token:  Some(token_response.refresh_token),
+        expires_in:     token_response.expires_in
+    }))
+}
+
+/// Get an access token for the service account with the JSON key at `key_path`. Service accounts have no refresh token,
+/// instead a new JWT assertion signed with the account's private key is exchanged every time
+///
+/// ## Errors
+/// - When the key can't be read or isn't a valid service account key
+/// - When signing the assertion fails
+/// - When Google refuses the assertion
+/// - Reqwest error
+pub async fn service_account_token(env: &Env, http: &reqwest::Client, key_path: &str) -> Result<LoginData> {
+    let key = read_service_account_key(key_path).with_path(key_path)?;
+    let token_url = token_url();
+    let assertion = service_account_assertion(&key, &token_url, chrono::Utc::now().timestamp())?;
+
+    let request = JwtBearerRequest {
+        grant_type: JWT_BEARER_GRANT_TYPE,
+        assertion:  &assertion
+    };
+
+    let body = unwrap_other_err!(serde_json::to_string(&request));
+    let response = retry::send(env.max_attempts, || async { Ok(http.post(&token_url).body(body.clone())) }).await?;
+
+    if !response.status().is_success() {
+        let error: OAuthError = unwrap_req_err!(response.json().await);
+        return Err(oauth_error(error));
+    }
+
+    let payload: RefreshTokenResponse = unwrap_req_err!(response.json().await);
+    Ok(LoginData {
+        access_token:   payload.access_token,
+        expires_in:     payload.expires_in,
+        refresh_token:  None
+    })
+}
+
+/// Read the JSON key of a service account
+///
+/// ## Errors
+/// - When the file can't be read
+/// - When the file isn't a service account key
+fn read_service_account_key(key_path: &str) -> Result<ServiceAccountKey> {
+    let contents = unwrap_io_err!(std::fs::read_to_string(key_path));
+    Ok(unwrap_other_err!(serde_json::from_str(&contents)))
+}
+
+/// Create the JWT assertion of a service account for the token endpoint `audience`, signed with RS256 using the account's private key
+///
+/// ## Errors
+/// - When the private key can't be parsed
+/// - When signing fails
+fn service_account_assertion(key: &ServiceAccountKey, audience: &str, now: i64) -> Result<String> {
+    let header = JwtHeader {
+        alg:    "RS256",
+        typ:    "JWT"
+    };
+
+    let claims = JwtClaims {
+        iss:    &key.client_email,
+        scope:  SERVICE_ACCOUNT_SCOPE,
+        aud:    audience,
+        iat:    now,
+        exp:    now + ASSERTION_LIFETIME
+    };
+
+    let signing_input = format!("{}.{}",
+        base64::encode_config(unwrap_other_err!(serde_json::to_vec(&header)), base64::URL_SAFE_NO_PAD),
+        base64::encode_config(unwrap_other_err!(serde_json::to_vec(&claims)), base64::URL_SAFE_NO_PAD));
+
+    let private_key = unwrap_other_err!(PKey::private_key_from_pem(key.private_key.as_bytes()));
+    let mut signer = unwrap_other_err!(Signer::new(MessageDigest::sha256(), &private_key));
+    unwrap_other_err!(signer.update(signing_input.as_bytes()));
+    let signature = unwrap_other_err!(signer.sign_to_vec());
+
+    Ok(format!("{}.{}", signing_input, base64::encode_config(signature, base64::URL_SAFE_NO_PAD)))
+}
+
+/// Revoke a refresh or access token, so it can't be used anymore
+///
+/// ## Errors
+/// - When Google refuses to revoke the token, e.g. because it was already revoked
+/// - Reqwest error
+pub async fn revoke_token(env: &Env, http: &reqwest::Client, token: &str) -> Result<()> {
+    let uri = format!("{}/revoke", api::base_url(OAUTH_URL_VAR, OAUTH_URL));
+    let response = retry::send(env.max_attempts, || async { Ok(http.post(&uri).form(&[("token", token)])) }).await?;
+
+    if !response.status().is_success() {
+        let error: OAuthError = unwrap_req_err!(response.json().await);
+        return Err(oauth_error(error));
+    }
+
+    Ok(())
+}
+
+/// Turn an error returned by the OAuth2 endpoints into an Error
+fn oauth_error(error: OAuthError) -> crate::Error {
+    match error.error_description {
+        Some(description) => new_err!(ErrorKind::Other(format!("Google refused the login: {} ({})", description, error.error))),
+        None => new_err!(ErrorKind::Other(format!("Google refused the login: {}", error.error)))
+    }
+}
+
+/// Struct providing the access tokens of the logged in user or the configured service account, refreshing them when they are about to expire
+pub struct TokenProvider {
+    /// Env instance
+    env:    Env,
+
+    /// The HTTP client tokens are refreshed with
+    http:   reqwest::Client,
+
+    /// The current access token and the moment it expires, in seconds since the epoch. Also held while refreshing,
+    /// so concurrent requests don't all refresh the token
+    cached: Mutex<Option<(String, i64)>>
+}
+
+impl TokenProvider {
+    /// Create a token provider for the service account configured in `env`, or the user logged in according to its database
+    pub fn new(env: &Env, http: reqwest::Client) -> Self {
+        Self {
+            env:    env.clone(),
+            http,
+            cached: Mutex::new(None)
+        }
+    }
+
+    /// Get an access token, of the configured service account if there is one, otherwise of the logged in user
+    ///
+    /// ## Errors
+    /// - When a database error occurs
+    /// - When the key of the service account can't be used
+    /// - When the Google API returns an error
+    /// - When reqwest returns an error
+    pub async fn access_token(&self) -> Result<String> {
+        let mut cached = self.cached.lock().await;
+        if let Some((access_token, expiry)) = &*cached {
+            if chrono::Utc::now().timestamp() <= (expiry - 60) {
+                return Ok(access_token.clone());
+            }
+        }
+
+        if let Some(key_path) = &self.env.service_account_key {
+            let token = service_account_token(&self.env, &self.http, key_path).await?;
+            *cached = Some((token.access_token.clone(), chrono::Utc::now().timestamp() + token.expires_in));
+            return Ok(token.access_token);
+        }
+
+        let (access_token, refresh_token, expiry) = match stored_tokens(&self.env)? {
+            Some(tokens) => tokens,
+            None => return Ok(String::default())
+        };
 
         if chrono::Utc::now().timestamp() > (expiry - 60) {
-            // We need to manually drop these to avoid having two open connections at the same time
-            // Since sqlite won't allow that
-            drop(result);
-            drop(stmt);
-            drop(conn);
-            let new_token = refresh_access_token(env, &refresh_token)?;
-            crate::login::db::save_to_database(&new_token, env)?;
+            let new_token = refresh_access_token(&self.env, &self.http, &refresh_token).await?;
+            crate::login::db::save_to_database(&new_token, &self.env)?;
 
+            *cached = Some((new_token.access_token.clone(), chrono::Utc::now().timestamp() + new_token.expires_in));
             return Ok(new_token.access_token);
         }
 
-        return Ok(access_token)
+        *cached = Some((access_token.clone(), expiry));
+        Ok(access_token)
     }
+}
 
-    Ok(String::default())
+/// Get the access token, the refresh token and the access token's expiry time of the logged in user from the database
+///
+/// ## Errors
+/// - When a database error occurs
+pub fn stored_tokens(env: &Env) -> Result<Option<(String, String, i64)>> {
+    let conn = unwrap_db_err!(env.get_conn());
+    let mut stmt = unwrap_db_err!(conn.prepare("SELECT access_token, refresh_token, expiry FROM user WHERE profile = :profile"));
+    let mut result = unwrap_db_err!(stmt.query(rusqlite::named_params! {
+        ":profile": &env.profile
+    }));
 
+    if let Ok(Some(row)) = result.next() {
+        let access_token = unwrap_db_err!(row.get::<&str, String>("access_token"));
+        let refresh_token = unwrap_db_err!(row.get::<&str, String>("refresh_token"));
+        let expiry = unwrap_db_err!(row.get::<&str, i64>("expiry"));
+
+        return Ok(Some((access_token, refresh_token, expiry)));
+    }
+
+    Ok(None)
 }
 
 /// Refresh an OAuth2 access token using a refresh token
@@ -195,7 +553,7 @@ pub fn get_access_token(env: &Env) -> Result<String> {
 /// ## Errors
 /// - When the Google API returns an error
 /// - When reqwest returns an error
-fn refresh_access_token(env: &Env, refresh_token: &str) -> Result<LoginData> {
+async fn refresh_access_token(env: &Env, http: &reqwest::Client, refresh_token: &str) -> Result<LoginData> {
     let request_body = RefreshTokenRequest {
         client_id:      &env.client_id,
         client_secret:  &env.client_secret,
@@ -205,11 +563,10 @@ fn refresh_access_token(env: &Env, refresh_token: &str) -> Result<LoginData> {
 
     //Safe to unwrap() because we know the struct can be translated to valid json
     let body = serde_json::to_string(&request_body).unwrap();
-    let request = unwrap_req_err!(reqwest::blocking::Client::new().post("https://oauth2.googleapis.com/token")
-        .body(body)
-        .send());
+    let token_url = token_url();
+    let request = retry::send(env.max_attempts, || async { Ok(http.post(&token_url).body(body.clone())) }).await?;
 
-    let response_payload: GoogleResponse<RefreshTokenResponse> = unwrap_req_err!(request.json());
+    let response_payload: GoogleResponse<RefreshTokenResponse> = unwrap_req_err!(request.json().await);
     let payload = unwrap_google_err!(response_payload);
 
     Ok(LoginData {
@@ -217,4 +574,36 @@ fn refresh_access_token(env: &Env, refresh_token: &str) -> Result<LoginData> {
         expires_in: payload.expires_in,
         refresh_token: None
     })
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ServiceAccountKey, service_account_assertion};
+    use openssl::hash::MessageDigest;
+    use openssl::pkey::PKey;
+    use openssl::rsa::Rsa;
+    use openssl::sign::Verifier;
+
+    #[test]
+    fn assertion_is_signed_with_the_private_key() {
+        let private_key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let key = ServiceAccountKey {
+            client_email:   "backup@project.iam.gserviceaccount.com".to_string(),
+            private_key:    String::from_utf8(private_key.private_key_to_pem_pkcs8().unwrap()).unwrap()
+        };
+
+        let assertion = service_account_assertion(&key, "https://oauth2.googleapis.com/token", 1000).unwrap();
+        let parts: Vec<&str> = assertion.split('.').collect();
+        assert_eq!(3, parts.len());
+
+        let claims: serde_json::Value = serde_json::from_slice(&base64::decode_config(parts[1], base64::URL_SAFE_NO_PAD).unwrap()).unwrap();
+        assert_eq!("backup@project.iam.gserviceaccount.com", claims["iss"]);
+        assert_eq!("https://oauth2.googleapis.com/token", claims["aud"]);
+        assert_eq!(4600, claims["exp"]);
+
+        let signature = base64::decode_config(parts[2], base64::URL_SAFE_NO_PAD).unwrap();
+        let mut verifier = Verifier::new(MessageDigest::sha256(), &private_key).unwrap();
+        verifier.update(format!("{}.{}", parts[0], parts[1]).as_bytes()).unwrap();
+        assert!(verifier.verify(&signature).unwrap());
+    }
+}
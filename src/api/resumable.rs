@@ -0,0 +1,321 @@
+//! Resumable uploads to Google Drive, used for large files. The session of an upload is stored in the database,
+//! so an upload which was interrupted can be continued during the next run. Sessions are stored by profile, root folder and local path
+
+use crate::api::GoogleResponse;
+use crate::api::drive::DriveClient;
+use crate::api::retry;
+use crate::env::Env;
+use crate::{Result, ErrorKind, unwrap_db_err, unwrap_req_err, unwrap_google_err, unwrap_other_err, unwrap_io_err, new_err};
+use rusqlite::named_params;
+use std::io::SeekFrom;
+use std::path::Path;
+use std::time::SystemTime;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+/// Files of this size in bytes or larger are uploaded with a resumable upload
+pub const RESUMABLE_THRESHOLD: u64 = 5 * 1024 * 1024;
+
+/// The size of the chunks a file is uploaded in. Google requires this to be a multiple of 256 KiB
+const CHUNK_SIZE: u64 = 16 * 256 * 1024;
+
+/// Struct describing the request which starts a resumable upload
+pub struct UploadRequest<'a> {
+    /// The HTTP method, `POST` to create a file or `PATCH` to update one
+    pub method:     reqwest::Method,
+
+    /// The URI to start the upload at, with `uploadType=resumable`
+    pub uri:        String,
+
+    /// The file's metadata, serialized to JSON
+    pub metadata:   String,
+
+    /// The MIME type of the file's content
+    pub mime_type:  &'a str,
+
+    /// The ID of the file in Google Drive once uploaded
+    pub file_id:    &'a str,
+
+    /// The ID of the parent folder when creating a file, or the ID of the file when updating one.
+    /// A stored session is only resumed if it was started for the same target
+    pub target:     &'a str
+}
+
+/// Struct describing a stored upload session
+struct UploadSession {
+    /// The URI of the session, to which the chunks are uploaded
+    session_uri:    String,
+
+    /// The ID of the file in Google Drive once uploaded
+    file_id:        String,
+
+    /// See `UploadRequest::target`
+    target:         String,
+
+    /// The size of the local file when the session was started
+    size:           i64,
+
+    /// The modification time of the local file when the session was started, in seconds since the epoch
+    mtime:          i64
+}
+
+/// Enum describing the progress of an upload session
+enum Progress {
+    /// Google has received all bytes before the offset
+    Incomplete(u64),
+
+    /// The upload is complete
+    Complete,
+
+    /// The session no longer exists
+    Expired
+}
+
+/// Upload a file with a resumable upload, continuing a stored session for the same file if there is one. Returns the ID of the file
+///
+/// ## Errors
+/// - Request failure
+/// - Google API error
+/// - When reading the local file fails
+/// - When a database operation fails
+pub async fn upload(client: &DriveClient, path: &Path, request: UploadRequest<'_>) -> Result<String> {
+    let env = client.env();
+    let (size, mtime) = size_and_mtime(path)?;
+
+    let mut resumed = None;
+    if let Some(session) = load_session(env, path)? {
+        if session.target == request.target && session.size == size as i64 && session.mtime == mtime {
+            match query_progress(client, &session.session_uri, size).await? {
+                Progress::Incomplete(offset) => {
+                    println!("Info: Resuming upload of '{}' at {} of {} bytes", path.to_str().unwrap(), offset, size);
+                    resumed = Some((session, offset));
+                },
+                Progress::Complete => {
+                    remove_session(env, path)?;
+                    return Ok(session.file_id);
+                },
+                Progress::Expired => remove_session(env, path)?
+            }
+        } else {
+            // The file changed, or is now uploaded to a different place
+            remove_session(env, path)?;
+        }
+    }
+
+    let (session, mut offset) = match resumed {
+        Some(resumed) => resumed,
+        None => {
+            let session = UploadSession {
+                session_uri:    start_session(client, &request, size).await?,
+                file_id:        request.file_id.to_string(),
+                target:         request.target.to_string(),
+                size:           size as i64,
+                mtime
+            };
+
+            save_session(env, path, &session)?;
+            (session, 0)
+        }
+    };
+
+    let max_attempts = env.max_attempts.unwrap_or(retry::DEFAULT_MAX_ATTEMPTS).max(1);
+    let mut failures = 0;
+    let mut file = unwrap_io_err!(tokio::fs::File::open(path).await);
+    while offset < size {
+        unwrap_io_err!(file.seek(SeekFrom::Start(offset)).await);
+        let mut chunk = Vec::with_capacity(CHUNK_SIZE as usize);
+        unwrap_io_err!((&mut file).take(CHUNK_SIZE).read_to_end(&mut chunk).await);
+
+        // Sent once: Google may have received part of a chunk which failed, so the same bytes can't simply be sent again
+        let end = offset + chunk.len() as u64 - 1;
+        let result = retry::send(Some(1), || async {
+            let access_token = client.access_token().await?;
+            Ok(client.http().put(&session.session_uri)
+                .header("Authorization", &format!("Bearer {}", access_token))
+                .header("Content-Range", &format!("bytes {}-{}/{}", offset, end, size))
+                .body(chunk.clone()))
+        }).await;
+
+        let response = match result {
+            Ok(response) => response,
+            Err(e) if retry::is_transient(&e) && failures + 1 < max_attempts => {
+                failures += 1;
+                let delay = retry::backoff(failures);
+                eprintln!("Warning: Uploading a chunk of '{}' failed temporarily, resuming in {:.1}s (attempt {} of {})", path.to_str().unwrap(), delay.as_secs_f64(), failures + 1, max_attempts);
+                tokio::time::sleep(delay).await;
+
+                // Continue from what Google actually received
+                match query_progress(client, &session.session_uri, size).await? {
+                    Progress::Incomplete(new_offset) => {
+                        offset = new_offset;
+                        continue;
+                    },
+                    Progress::Complete => break,
+                    Progress::Expired => {
+                        remove_session(env, path)?;
+                        return Err(new_err!(ErrorKind::Other(format!("The upload session of '{}' expired", path.to_str().unwrap()))));
+                    }
+                }
+            },
+            Err(e) => return Err(e)
+        };
+
+        match progress(response).await? {
+            Progress::Incomplete(new_offset) => offset = new_offset,
+            Progress::Complete => break,
+            Progress::Expired => {
+                remove_session(env, path)?;
+                return Err(new_err!(ErrorKind::Other(format!("The upload session of '{}' expired", path.to_str().unwrap()))));
+            }
+        }
+    }
+
+    remove_session(env, path)?;
+    Ok(session.file_id)
+}
+
+/// Start a resumable upload session and return its URI
+///
+/// ## Errors
+/// - Request failure
+/// - Google API error
+async fn start_session(client: &DriveClient, request: &UploadRequest<'_>, size: u64) -> Result<String> {
+    let response = client.send(|access_token| Ok(client.http().request(request.method.clone(), &request.uri)
+        .header("Authorization", &format!("Bearer {}", access_token))
+        .header("Content-Type", "application/json; charset=UTF-8")
+        .header("X-Upload-Content-Type", request.mime_type)
+        .header("X-Upload-Content-Length", size)
+        .body(request.metadata.clone()))).await?;
+
+    let status = response.status();
+    let location = response.headers().get("Location").and_then(|location| location.to_str().ok()).map(str::to_string);
+    match location {
+        Some(location) if status.is_success() => Ok(location),
+        _ => {
+            let payload: GoogleResponse<()> = unwrap_req_err!(response.json().await);
+            unwrap_google_err!(payload);
+            Err(new_err!(ErrorKind::Other(format!("Starting an upload session failed with status {}", status))))
+        }
+    }
+}
+
+/// Ask Google how much of the file it has received in an upload session
+///
+/// ## Errors
+/// - Request failure
+/// - Google API error
+async fn query_progress(client: &DriveClient, session_uri: &str, size: u64) -> Result<Progress> {
+    let response = client.send(|access_token| Ok(client.http().put(session_uri)
+        .header("Authorization", &format!("Bearer {}", access_token))
+        .header("Content-Range", &format!("bytes */{}", size))
+        .header("Content-Length", 0))).await?;
+
+    progress(response).await
+}
+
+/// Get the progress of an upload session from the response to a request in that session
+///
+/// ## Errors
+/// - Google API error
+async fn progress(response: reqwest::Response) -> Result<Progress> {
+    match response.status().as_u16() {
+        200 | 201 => Ok(Progress::Complete),
+        // Resume Incomplete. This isn't followed as a redirect, as Google doesn't send a Location header with it
+        308 => Ok(Progress::Incomplete(received_bytes(response.headers().get("Range").and_then(|range| range.to_str().ok())))),
+        404 | 410 => Ok(Progress::Expired),
+        status => {
+            let payload: GoogleResponse<()> = unwrap_req_err!(response.json().await);
+            unwrap_google_err!(payload);
+            Err(new_err!(ErrorKind::Other(format!("Uploading a chunk failed with status {}", status))))
+        }
+    }
+}
+
+/// Get the number of bytes received from the `Range` header Google returns, e.g. `bytes=0-1023`. Without the header nothing was received
+fn received_bytes(range: Option<&str>) -> u64 {
+    range.and_then(|range| range.rsplit('-').next())
+        .and_then(|last_byte| last_byte.parse::<u64>().ok())
+        .map(|last_byte| last_byte + 1)
+        .unwrap_or(0)
+}
+
+/// Get the size in bytes and the modification time in seconds since the epoch of a local file
+///
+/// ## Errors
+/// - When fetching the file's metadata fails
+fn size_and_mtime(path: &Path) -> Result<(u64, i64)> {
+    let metadata = unwrap_io_err!(std::fs::metadata(path));
+    let mtime = unwrap_other_err!(unwrap_io_err!(metadata.modified()).duration_since(SystemTime::UNIX_EPOCH)).as_secs() as i64;
+    Ok((metadata.len(), mtime))
+}
+
+/// Get the stored upload session of a local file, if there is one
+///
+/// ## Errors
+/// - When a database operation fails
+fn load_session(env: &Env, path: &Path) -> Result<Option<UploadSession>> {
+    let conn = unwrap_db_err!(env.get_conn());
+    let mut stmt = unwrap_db_err!(conn.prepare("SELECT * FROM upload_sessions WHERE profile = :profile AND root_id = :root_id AND path = :path"));
+    let mut result = unwrap_db_err!(stmt.query(named_params! {
+        ":profile": &env.profile,
+        ":root_id": &env.root_folder,
+        ":path":    path.to_str().unwrap()
+    }));
+
+    match result.next() {
+        Ok(Some(row)) => Ok(Some(UploadSession {
+            session_uri:    unwrap_db_err!(row.get::<&str, String>("session_uri")),
+            file_id:        unwrap_db_err!(row.get::<&str, String>("file_id")),
+            target:         unwrap_db_err!(row.get::<&str, String>("target")),
+            size:           unwrap_db_err!(row.get::<&str, i64>("size")),
+            mtime:          unwrap_db_err!(row.get::<&str, i64>("mtime"))
+        })),
+        Ok(None) => Ok(None),
+        Err(e) => Err(new_err!(ErrorKind::Database(e)))
+    }
+}
+
+/// Store the upload session of a local file, replacing a previously stored session
+///
+/// ## Errors
+/// - When a database operation fails
+fn save_session(env: &Env, path: &Path, session: &UploadSession) -> Result<()> {
+    let conn = unwrap_db_err!(env.get_conn());
+    unwrap_db_err!(conn.execute("INSERT OR REPLACE INTO upload_sessions (profile, root_id, path, session_uri, file_id, target, size, mtime) VALUES (:profile, :root_id, :path, :session_uri, :file_id, :target, :size, :mtime)", named_params! {
+        ":root_id":     &env.root_folder,
+        ":path":        path.to_str().unwrap(),
+        ":session_uri": &session.session_uri,
+        ":file_id":     &session.file_id,
+        ":target":      &session.target,
+        ":size":        session.size,
+        ":mtime":       session.mtime,
+        ":profile":     &env.profile
+    }));
+
+    Ok(())
+}
+
+/// Remove the stored upload session of a local file
+///
+/// ## Errors
+/// - When a database operation fails
+fn remove_session(env: &Env, path: &Path) -> Result<()> {
+    let conn = unwrap_db_err!(env.get_conn());
+    unwrap_db_err!(conn.execute("DELETE FROM upload_sessions WHERE profile = :profile AND root_id = :root_id AND path = :path", named_params! {
+        ":profile": &env.profile,
+        ":root_id": &env.root_folder,
+        ":path":    path.to_str().unwrap()
+    }));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::received_bytes;
+
+    #[test]
+    fn received_bytes_from_range() {
+        assert_eq!(1024, received_bytes(Some("bytes=0-1023")));
+        assert_eq!(0, received_bytes(None));
+    }
+}
@@ -0,0 +1,167 @@
+//! Retrying requests to Google which failed in a way which may succeed later, e.g. because of rate limiting or a temporary server error
+
+use crate::api::{GoogleResponse, GoogleError};
+use crate::{Result, Error, ErrorKind, unwrap_req_err, new_err};
+use rand::Rng;
+use reqwest::StatusCode;
+use std::future::Future;
+use std::time::Duration;
+
+/// How often a request is attempted if the number of attempts isn't configured
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+
+/// The delay before the first retry, in milliseconds. It doubles with every retry
+const BASE_DELAY_MS: u64 = 1000;
+
+/// The longest delay between two attempts, in milliseconds, not counting the jitter
+const MAX_DELAY_MS: u64 = 64_000;
+
+/// The largest random delay added to the backoff, in milliseconds, so concurrent requests don't all retry at the same moment
+const MAX_JITTER_MS: u64 = 1000;
+
+/// The reasons Google gives for errors which may go away when the request is repeated
+const RETRYABLE_REASONS: &[&str] = &["userRateLimitExceeded", "rateLimitExceeded", "backendError", "internalError"];
+
+/// Enum describing the result of a single attempt
+enum Attempt {
+    /// The request succeeded, or failed in a way retrying won't fix. The caller handles the response
+    Done(reqwest::Response),
+
+    /// The request failed in a way which may succeed later, optionally after the delay Google asked for
+    Retry(Error, Option<Duration>)
+}
+
+/// Send a request, repeating it with jittered exponential backoff for as long as it fails in a way which may succeed later.
+/// `request` is called to build the request for every attempt. When Google sends a `Retry-After` header, that delay is used instead
+///
+/// ## Errors
+/// - When building the request fails
+/// - Request failure which isn't transient, or which persisted for `max_attempts` attempts
+/// - Google API error which isn't transient, or which persisted for `max_attempts` attempts
+pub async fn send<F, Fut>(max_attempts: Option<u32>, request: F) -> Result<reqwest::Response>
+where F: Fn() -> Fut, Fut: Future<Output = Result<reqwest::RequestBuilder>> {
+    let max_attempts = max_attempts.unwrap_or(DEFAULT_MAX_ATTEMPTS).max(1);
+
+    let mut attempt = 1;
+    loop {
+        let (error, retry_after) = match request().await?.send().await {
+            Ok(response) => match check(response).await? {
+                Attempt::Done(response) => return Ok(response),
+                Attempt::Retry(error, retry_after) => (error, retry_after)
+            },
+            Err(e) if e.is_timeout() || e.is_connect() => (new_err!(ErrorKind::Request(e)), None),
+            Err(e) => return Err(new_err!(ErrorKind::Request(e)))
+        };
+
+        if attempt >= max_attempts {
+            return Err(error);
+        }
+
+        let delay = retry_after.unwrap_or_else(|| backoff(attempt));
+        eprintln!("Warning: A request to Google failed temporarily, retrying in {:.1}s (attempt {} of {})", delay.as_secs_f64(), attempt + 1, max_attempts);
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// Check whether a response is an error which may go away when the request is repeated
+///
+/// ## Errors
+/// - When reading the body of an error response fails
+/// - Google API error which isn't transient
+async fn check(response: reqwest::Response) -> Result<Attempt> {
+    let status = response.status();
+    if status != StatusCode::FORBIDDEN && status != StatusCode::TOO_MANY_REQUESTS && !status.is_server_error() {
+        return Ok(Attempt::Done(response));
+    }
+
+    let retry_after = response.headers().get(reqwest::header::RETRY_AFTER)
+        .and_then(|retry_after| retry_after.to_str().ok())
+        .and_then(parse_retry_after);
+    let body = unwrap_req_err!(response.text().await);
+
+    match serde_json::from_str::<GoogleResponse<()>>(&body).ok().and_then(|payload| payload.error) {
+        Some(error) if is_retryable(&error) => Ok(Attempt::Retry(new_err!(ErrorKind::Google(error)), retry_after)),
+        Some(error) => Err(new_err!(ErrorKind::Google(error))),
+        // Not every server error comes with a body Google's API would send, e.g. when a proxy in between failed
+        None if status != StatusCode::FORBIDDEN => Ok(Attempt::Retry(new_err!(ErrorKind::Status(status)), retry_after)),
+        None => Err(new_err!(ErrorKind::Other(format!("Google responded with status {}: {}", status, body))))
+    }
+}
+
+/// Check whether a failed request may succeed when it is repeated: when the request timed out, or Google failed temporarily
+pub fn is_transient(error: &Error) -> bool {
+    match error.kind() {
+        ErrorKind::Google(e) => is_retryable(e),
+        ErrorKind::Request(e) => e.is_timeout() || e.is_connect(),
+        ErrorKind::Status(status) => *status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error(),
+        _ => false
+    }
+}
+
+/// Check whether an error returned by Google may go away when the request is repeated
+pub fn is_retryable(error: &GoogleError) -> bool {
+    error.code == 429 || error.code >= 500 || error.errors.iter().any(|data| RETRYABLE_REASONS.contains(&data.reason.as_str()))
+}
+
+/// Get the delay before the next attempt, after `attempt` attempts failed
+pub fn backoff(attempt: u32) -> Duration {
+    let delay = BASE_DELAY_MS.saturating_mul(1 << (attempt - 1).min(16)).min(MAX_DELAY_MS);
+    Duration::from_millis(delay + rand::thread_rng().gen_range(0..=MAX_JITTER_MS))
+}
+
+/// Parse the value of a `Retry-After` header, which is either a number of seconds or an HTTP date
+fn parse_retry_after(retry_after: &str) -> Option<Duration> {
+    if let Ok(seconds) = retry_after.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = chrono::DateTime::parse_from_rfc2822(retry_after.trim()).ok()?;
+    let seconds = (date.timestamp() - chrono::Utc::now().timestamp()).max(0);
+    Some(Duration::from_secs(seconds as u64))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{is_retryable, backoff, parse_retry_after, MAX_DELAY_MS, MAX_JITTER_MS};
+    use crate::api::{GoogleError, ErrorData};
+    use std::time::Duration;
+
+    /// Create an error as Google would return it, with a single reason
+    fn google_error(code: i16, reason: &str) -> GoogleError {
+        GoogleError {
+            code,
+            message:    String::new(),
+            errors:     vec![ErrorData {
+                domain:         "usageLimits".to_string(),
+                reason:         reason.to_string(),
+                message:        String::new(),
+                location_type:  None,
+                location:       None
+            }]
+        }
+    }
+
+    #[test]
+    fn retryable_errors() {
+        assert!(is_retryable(&google_error(403, "userRateLimitExceeded")));
+        assert!(is_retryable(&google_error(429, "rateLimitExceeded")));
+        assert!(is_retryable(&google_error(500, "backendError")));
+        assert!(!is_retryable(&google_error(403, "dailyLimitExceeded")));
+        assert!(!is_retryable(&google_error(404, "notFound")));
+    }
+
+    #[test]
+    fn backoff_grows_up_to_the_maximum() {
+        assert!(backoff(1) >= Duration::from_millis(1000) && backoff(1) <= Duration::from_millis(1000 + MAX_JITTER_MS));
+        assert!(backoff(3) >= Duration::from_millis(4000));
+        assert!(backoff(30) <= Duration::from_millis(MAX_DELAY_MS + MAX_JITTER_MS));
+    }
+
+    #[test]
+    fn retry_after() {
+        assert_eq!(Some(Duration::from_secs(30)), parse_retry_after("30"));
+        assert_eq!(Some(Duration::from_secs(0)), parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"));
+        assert_eq!(None, parse_retry_after("soon"));
+    }
+}
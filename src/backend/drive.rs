@@ -0,0 +1,91 @@
+//! The Google Drive backend
+
+use super::Backend;
+use crate::api::drive::{DriveClient, File, Change, FOLDER_MIME_TYPE};
+use crate::Result;
+use async_trait::async_trait;
+use std::path::Path;
+
+#[async_trait]
+impl Backend for DriveClient {
+    fn describe(&self) -> String {
+        "Drive".to_string()
+    }
+
+    fn describe_root(&self) -> String {
+        format!("root folder '{}'", self.env().root_folder_name)
+    }
+
+    async fn find_root(&self) -> Result<Option<String>> {
+        let list = self.list_files(Some(&format!("name = '{}' and mimeType = '{}' and trashed = false", escape(&self.env().root_folder_name), FOLDER_MIME_TYPE)), self.env().drive_id.as_deref()).await?;
+        Ok(list.into_iter().next().map(|file| file.id))
+    }
+
+    async fn create_root(&self) -> Result<String> {
+        let parent = self.env().drive_id.clone().unwrap_or_else(|| "root".to_string());
+        DriveClient::create_folder(self, &self.env().root_folder_name, &parent).await
+    }
+
+    async fn list_folder(&self, folder_id: &str) -> Result<Vec<File>> {
+        self.list_files(Some(&format!("'{}' in parents and trashed = false", folder_id)), self.env().drive_id.as_deref()).await
+    }
+
+    async fn find(&self, parent_id: &str, name: &str, folders_only: bool) -> Result<Vec<File>> {
+        let query = if folders_only {
+            format!("name = '{}' and mimeType = '{}' and trashed = false and '{}' in parents", escape(name), FOLDER_MIME_TYPE, parent_id)
+        } else {
+            format!("name = '{}' and trashed = false and '{}' in parents", escape(name), parent_id)
+        };
+
+        self.list_files(Some(&query), self.env().drive_id.as_deref()).await
+    }
+
+    async fn create_folder(&self, name: &str, parent_id: &str) -> Result<String> {
+        DriveClient::create_folder(self, name, parent_id).await
+    }
+
+    async fn upload_file(&self, path: &Path, parent_id: &str) -> Result<String> {
+        DriveClient::upload_file(self, path, parent_id).await
+    }
+
+    async fn update_file(&self, path: &Path, id: &str) -> Result<()> {
+        DriveClient::update_file(self, path, id).await
+    }
+
+    async fn download_file(&self, id: &str, path: &Path) -> Result<()> {
+        DriveClient::download_file(self, id, path).await
+    }
+
+    async fn delete_file(&self, id: &str) -> Result<()> {
+        DriveClient::delete_file(self, id).await
+    }
+
+    async fn trash_file(&self, id: &str) -> Result<()> {
+        DriveClient::trash_file(self, id).await
+    }
+
+    async fn start_page_token(&self) -> Result<Option<String>> {
+        self.get_start_page_token(self.env().drive_id.as_deref()).await.map(Some)
+    }
+
+    async fn list_changes(&self, page_token: &str) -> Result<(Vec<Change>, String)> {
+        DriveClient::list_changes(self, page_token, self.env().drive_id.as_deref()).await
+    }
+}
+
+/// Escape a value for use within single quotes in a Drive query, i.e. a name containing `\` or `'`
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+#[cfg(test)]
+mod test {
+    use super::escape;
+
+    #[test]
+    fn quotes_and_backslashes_are_escaped() {
+        assert_eq!("Notes", escape("Notes"));
+        assert_eq!("Bob\\'s notes", escape("Bob's notes"));
+        assert_eq!("a\\\\b\\\\\\'", escape("a\\b\\'"));
+    }
+}
@@ -0,0 +1,233 @@
+//! The local directory backend, which mirrors the synced files into a directory, e.g. on a NAS mount.
+//! The ID of an entry is its absolute path
+
+use super::Backend;
+use crate::api::drive::{File, FOLDER_MIME_TYPE};
+use crate::sync::md5_checksum;
+use crate::{Result, ErrorKind, unwrap_io_err, unwrap_other_err, new_err};
+use async_trait::async_trait;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// The name of the directory in the target directory removed entries are moved to, unless they are deleted permanently
+const TRASH_DIR: &str = ".gsync-trash";
+
+/// Struct describing a local directory files are synced to
+pub struct LocalBackend {
+    /// The absolute path of the directory everything is synced into
+    root:   PathBuf
+}
+
+impl LocalBackend {
+    /// Create a backend syncing into the directory `root`
+    ///
+    /// # Errors
+    /// - When the absolute path of `root` can't be determined
+    pub fn new(root: &Path) -> Result<Self> {
+        let root = if root.is_absolute() {
+            root.to_path_buf()
+        } else {
+            unwrap_io_err!(std::env::current_dir()).join(root)
+        };
+
+        Ok(Self { root })
+    }
+
+    /// Describe the entry at `path`, as the Drive API would. The checksum is left out, as reading every listed file would be expensive,
+    /// see `checksum`
+    ///
+    /// # Errors
+    /// - When reading the entry's metadata fails
+    fn entry(path: &Path) -> Result<File> {
+        let metadata = unwrap_io_err!(std::fs::metadata(path));
+        let modified = unwrap_io_err!(metadata.modified());
+        let modified_time = chrono::DateTime::<chrono::Utc>::from(modified).to_rfc3339();
+        let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        let parents = path.parent().map(|parent| vec![id(parent)]).unwrap_or_default();
+
+        if metadata.is_dir() {
+            return Ok(File {
+                id:             id(path),
+                name,
+                mime_type:      FOLDER_MIME_TYPE.to_string(),
+                modified_time,
+                md5_checksum:   None,
+                size:           None,
+                parents
+            });
+        }
+
+        Ok(File {
+            id:             id(path),
+            name,
+            mime_type:      mime_guess::from_path(path).first_or_octet_stream().essence_str().to_string(),
+            modified_time,
+            md5_checksum:   None,
+            size:           Some(metadata.len() as i64),
+            parents
+        })
+    }
+}
+
+#[async_trait]
+impl Backend for LocalBackend {
+    fn describe(&self) -> String {
+        format!("'{}'", self.root.to_str().unwrap())
+    }
+
+    fn describe_root(&self) -> String {
+        format!("target directory '{}'", self.root.to_str().unwrap())
+    }
+
+    async fn find_root(&self) -> Result<Option<String>> {
+        Ok(Some(id(&self.root)).filter(|_| self.root.is_dir()))
+    }
+
+    async fn create_root(&self) -> Result<String> {
+        unwrap_io_err!(tokio::fs::create_dir_all(&self.root).await);
+        Ok(id(&self.root))
+    }
+
+    async fn list_folder(&self, folder_id: &str) -> Result<Vec<File>> {
+        let folder = Path::new(folder_id);
+        let mut entries = unwrap_io_err!(tokio::fs::read_dir(folder).await);
+
+        let mut files = Vec::new();
+        while let Some(entry) = unwrap_io_err!(entries.next_entry().await) {
+            let path = entry.path();
+            if folder == self.root && entry.file_name() == TRASH_DIR {
+                continue;
+            }
+
+            files.push(Self::entry(&path)?);
+        }
+
+        Ok(files)
+    }
+
+    async fn find(&self, parent_id: &str, name: &str, folders_only: bool) -> Result<Vec<File>> {
+        let path = Path::new(parent_id).join(name);
+        if !path.exists() || (folders_only && !path.is_dir()) {
+            return Ok(Vec::new());
+        }
+
+        Ok(vec![Self::entry(&path)?])
+    }
+
+    async fn checksum(&self, file: &File) -> Result<Option<String>> {
+        if file.mime_type == FOLDER_MIME_TYPE {
+            return Ok(None);
+        }
+
+        md5_checksum(Path::new(&file.id)).map(Some)
+    }
+
+    async fn create_folder(&self, name: &str, parent_id: &str) -> Result<String> {
+        let path = Path::new(parent_id).join(name);
+        unwrap_io_err!(tokio::fs::create_dir_all(&path).await);
+        Ok(id(&path))
+    }
+
+    async fn upload_file(&self, path: &Path, parent_id: &str) -> Result<String> {
+        let name = match path.file_name() {
+            Some(name) => name,
+            None => return Err(new_err!(ErrorKind::Other("Missing file name".to_string())))
+        };
+
+        let target = Path::new(parent_id).join(name);
+        unwrap_io_err!(tokio::fs::copy(path, &target).await);
+        Ok(id(&target))
+    }
+
+    async fn update_file(&self, path: &Path, id: &str) -> Result<()> {
+        unwrap_io_err!(tokio::fs::copy(path, id).await);
+        Ok(())
+    }
+
+    async fn download_file(&self, id: &str, path: &Path) -> Result<()> {
+        super::download_atomically(path, |partial| async move {
+            unwrap_io_err!(tokio::fs::copy(id, partial).await);
+            Ok(())
+        }).await
+    }
+
+    async fn delete_file(&self, id: &str) -> Result<()> {
+        let path = Path::new(id);
+        if path.is_dir() {
+            unwrap_io_err!(tokio::fs::remove_dir_all(path).await);
+        } else {
+            unwrap_io_err!(tokio::fs::remove_file(path).await);
+        }
+
+        Ok(())
+    }
+
+    async fn trash_file(&self, id: &str) -> Result<()> {
+        let path = Path::new(id);
+        let relative = match path.strip_prefix(&self.root) {
+            Ok(relative) => relative,
+            Err(_) => return Err(new_err!(ErrorKind::Other(format!("'{}' is not in the target directory", id))))
+        };
+
+        // Entries trashed earlier with the same path are kept apart by the moment they were trashed
+        let trashed_at = unwrap_other_err!(SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)).as_secs();
+        let trash_path = self.root.join(TRASH_DIR).join(trashed_at.to_string()).join(relative);
+        if let Some(parent) = trash_path.parent() {
+            unwrap_io_err!(tokio::fs::create_dir_all(parent).await);
+        }
+
+        unwrap_io_err!(tokio::fs::rename(path, &trash_path).await);
+        Ok(())
+    }
+}
+
+/// Get the ID of the entry at `path`
+fn id(path: &Path) -> String {
+    path.to_string_lossy().to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{LocalBackend, TRASH_DIR};
+    use md5::Digest;
+    use crate::backend::Backend;
+    use crate::api::drive::FOLDER_MIME_TYPE;
+    use std::path::PathBuf;
+
+    /// Create an empty directory for a test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("gsync-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn upload_list_and_trash() {
+        let dir = test_dir("local-backend");
+        let source = dir.join("a.txt");
+        std::fs::write(&source, b"contents").unwrap();
+
+        let backend = LocalBackend::new(&dir.join("target")).unwrap();
+        assert_eq!(None, backend.find_root().await.unwrap());
+        let root = backend.create_root().await.unwrap();
+        let folder = backend.create_folder("docs", &root).await.unwrap();
+        let file = backend.upload_file(&source, &folder).await.unwrap();
+
+        let listing = backend.list_folder(&root).await.unwrap();
+        assert_eq!(1, listing.len());
+        assert_eq!(FOLDER_MIME_TYPE, listing[0].mime_type);
+
+        let found = backend.find(&folder, "a.txt", false).await.unwrap();
+        assert_eq!(None, found[0].md5_checksum);
+        assert_eq!(Some(format!("{:x}", md5::Md5::digest(b"contents"))), backend.checksum(&found[0]).await.unwrap());
+        assert!(backend.find(&folder, "a.txt", true).await.unwrap().is_empty());
+
+        backend.trash_file(&file).await.unwrap();
+        assert!(backend.list_folder(&folder).await.unwrap().is_empty());
+        assert!(dir.join("target").join(TRASH_DIR).is_dir());
+        assert_eq!(1, backend.list_folder(&root).await.unwrap().len());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
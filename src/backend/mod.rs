@@ -0,0 +1,168 @@
+//! Storage backends files are synced to. Syncing and restoring work against the `Backend` trait,
+//! which is implemented for Google Drive and for a plain local directory
+
+mod drive;
+mod local;
+
+pub use local::LocalBackend;
+
+use crate::api::drive::{File, Change};
+use crate::{Result, ErrorKind, new_err, unwrap_io_err};
+use async_trait::async_trait;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+
+/// The extension of the temporary file a download is written to, before it replaces the local file
+pub const PARTIAL_DOWNLOAD_EXTENSION: &str = "gsync-partial";
+
+/// Trait describing a place files can be synced to. Entries are identified by an ID chosen by the backend
+#[async_trait]
+pub trait Backend: Send + Sync {
+    /// Describe where the backend stores files, for log messages, e.g. `Drive`
+    fn describe(&self) -> String;
+
+    /// Describe the folder everything is synced into, for log messages, e.g. `root folder 'GSync'`
+    fn describe_root(&self) -> String;
+
+    /// Get the ID of the folder everything is synced into, if it exists
+    ///
+    /// # Errors
+    /// - When looking up the folder fails
+    async fn find_root(&self) -> Result<Option<String>>;
+
+    /// Create the folder everything is synced into, and return its ID
+    ///
+    /// # Errors
+    /// - When creating the folder fails
+    async fn create_root(&self) -> Result<String>;
+
+    /// List the entries in a folder
+    ///
+    /// # Errors
+    /// - When listing the folder fails
+    async fn list_folder(&self, folder_id: &str) -> Result<Vec<File>>;
+
+    /// Find the entries named `name` in a folder. If `folders_only` is set, files with that name are ignored
+    ///
+    /// # Errors
+    /// - When looking up the entries fails
+    async fn find(&self, parent_id: &str, name: &str, folders_only: bool) -> Result<Vec<File>>;
+
+    /// Create a folder, and return its ID
+    ///
+    /// # Errors
+    /// - When creating the folder fails
+    async fn create_folder(&self, name: &str, parent_id: &str) -> Result<String>;
+
+    /// Upload a local file as a new file in a folder, and return its ID
+    ///
+    /// # Errors
+    /// - When reading the local file or writing the new file fails
+    async fn upload_file(&self, path: &Path, parent_id: &str) -> Result<String>;
+
+    /// Replace the contents of an existing file with those of a local file
+    ///
+    /// # Errors
+    /// - When reading the local file or writing the existing file fails
+    async fn update_file(&self, path: &Path, id: &str) -> Result<()>;
+
+    /// Download the contents of a file to a local path, overwriting the local file if it exists.
+    /// The local file is only replaced once the download is complete, see `download_atomically`
+    ///
+    /// # Errors
+    /// - When reading the file or writing the local file fails
+    async fn download_file(&self, id: &str, path: &Path) -> Result<()>;
+
+    /// Permanently delete a file or folder
+    ///
+    /// # Errors
+    /// - When deleting fails
+    async fn delete_file(&self, id: &str) -> Result<()>;
+
+    /// Move a file or folder to the trash
+    ///
+    /// # Errors
+    /// - When moving the entry fails
+    async fn trash_file(&self, id: &str) -> Result<()>;
+
+    /// Get the MD5 checksum of a file listed by this backend. Returns `None` for entries without binary contents, e.g. Google Docs.
+    /// Backends for which computing the checksum is expensive leave `md5_checksum` empty when listing, and compute it here
+    ///
+    /// # Errors
+    /// - When computing the checksum fails
+    async fn checksum(&self, file: &File) -> Result<Option<String>> {
+        Ok(file.md5_checksum.clone())
+    }
+
+    /// Get the page token from which changes made after this moment can be listed with `list_changes`.
+    /// Returns `None` if the backend can't list changes, in which case every folder is listed in a two-way sync
+    ///
+    /// # Errors
+    /// - When getting the page token fails
+    async fn start_page_token(&self) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// List all changes made since the page token was obtained. Returns the changes and the page token from which future changes can be listed
+    ///
+    /// # Errors
+    /// - When listing the changes fails
+    /// - When the backend can't list changes
+    async fn list_changes(&self, _page_token: &str) -> Result<(Vec<Change>, String)> {
+        Err(new_err!(ErrorKind::Other("Listing changes is not supported by this backend".to_string())))
+    }
+}
+
+/// Get the path of the temporary file a download to `path` is written to: a hidden file in the same directory, so it can be renamed into place
+pub fn partial_download_path(path: &Path) -> PathBuf {
+    path.with_file_name(format!(".{}.{}", path.file_name().unwrap().to_str().unwrap(), PARTIAL_DOWNLOAD_EXTENSION))
+}
+
+/// Download a file to `path`. `write` writes the contents to the temporary file it is given, which then replaces `path`,
+/// so the local file is left untouched when the download fails or is interrupted
+///
+/// # Errors
+/// - The error of `write`
+/// - When replacing the local file fails
+pub async fn download_atomically<F, Fut>(path: &Path, write: F) -> Result<()>
+where F: FnOnce(PathBuf) -> Fut, Fut: Future<Output = Result<()>> {
+    let partial = partial_download_path(path);
+    if let Err(e) = write(partial.clone()).await {
+        let _ = tokio::fs::remove_file(&partial).await;
+        return Err(e);
+    }
+
+    unwrap_io_err!(tokio::fs::rename(&partial, path).await);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{download_atomically, partial_download_path};
+    use crate::{ErrorKind, new_err};
+
+    #[tokio::test]
+    async fn failed_download_leaves_the_local_file() {
+        let dir = std::env::temp_dir().join(format!("gsync-download-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("notes.txt");
+        std::fs::write(&path, "local").unwrap();
+
+        let result = download_atomically(&path, |partial| async move {
+            std::fs::write(&partial, "half of the remo").unwrap();
+            Err(new_err!(ErrorKind::Other("Connection reset".to_string())))
+        }).await;
+
+        assert!(result.is_err());
+        assert_eq!("local", std::fs::read_to_string(&path).unwrap());
+        assert!(!partial_download_path(&path).exists());
+
+        download_atomically(&path, |partial| async move {
+            std::fs::write(&partial, "remote").unwrap();
+            Ok(())
+        }).await.unwrap();
+
+        assert_eq!("remote", std::fs::read_to_string(&path).unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
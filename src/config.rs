@@ -2,7 +2,7 @@
 
 use crate::env::Env;
 use rusqlite::named_params;
-use crate::{Result, unwrap_db_err, Error};
+use crate::{Result, ErrorKind, unwrap_db_err, new_err};
 
 /// Struct describing a configuration for GSync
 #[derive(Debug)]
@@ -17,14 +17,32 @@ pub struct Configuration {
     pub input_files:    Option<String>,
 
     /// If using a Team Drive/Shared Drive, the ID of that drive
-    pub drive_id:       Option<String>
+    pub drive_id:       Option<String>,
+
+    /// Gitignore patterns of files to sync even if they are ignored, comma seperated
+    pub include_patterns:   Option<String>,
+
+    /// Gitignore patterns of files to never sync, comma seperated
+    pub exclude_patterns:   Option<String>,
+
+    /// The number of results to request per page when listing files in Google Drive
+    pub page_size:          Option<u32>,
+
+    /// How often a request to Google is attempted before giving up, when it fails in a way which may succeed later
+    pub max_attempts:       Option<u32>,
+
+    /// The path to the JSON key of the service account to authenticate with, instead of a logged in user
+    pub service_account_key:    Option<String>,
+
+    /// The name of the root folder in Google Drive
+    pub root_folder:        Option<String>
 }
 
 impl Configuration {
 
     /// Check if all fields in the current configuration are empty
     pub fn is_empty(&self) -> bool {
-        self.input_files.is_none() && self.client_id.is_none() && self.client_secret.is_none() && self.drive_id.is_none()
+        self.input_files.is_none() && self.client_id.is_none() && self.client_secret.is_none() && self.drive_id.is_none() && self.include_patterns.is_none() && self.exclude_patterns.is_none() && self.page_size.is_none() && self.max_attempts.is_none() && self.service_account_key.is_none() && self.root_folder.is_none()
     }
 
     /// Create an empty configuration
@@ -33,20 +51,25 @@ impl Configuration {
             client_id:      None,
             client_secret:  None,
             input_files:    None,
-            drive_id:       None
+            drive_id:       None,
+            include_patterns:   None,
+            exclude_patterns:   None,
+            page_size:          None,
+            max_attempts:       None,
+            service_account_key:    None,
+            root_folder:        None
         }
     }
 
     /// Check if the current configuration is complete, i.e. all required fields are set
     pub fn is_complete(&self) -> (bool, &str) {
-        // Self::drive_id is allowed to be None
+        // Self::drive_id is allowed to be None. A service account doesn't need the client ID and secret.
+        // Self::input_files is allowed to be None as well, when only sync jobs are used
 
-        if self.client_id.is_none() {
+        if self.client_id.is_none() && self.service_account_key.is_none() {
             (false, "'client_id' is empty")
-        } else if self.client_secret.is_none() {
+        } else if self.client_secret.is_none() && self.service_account_key.is_none() {
             (false, "'client_secret' is empty")
-        } else if self.input_files.is_none() {
-            (false, "'input_files' is empty")
         } else {
             (true, "")
         }
@@ -75,18 +98,50 @@ impl Configuration {
             None => output.drive_id = b.drive_id
         }
 
+        match a.include_patterns {
+            Some(s) => output.include_patterns = Some(s),
+            None => output.include_patterns = b.include_patterns
+        }
+
+        match a.exclude_patterns {
+            Some(s) => output.exclude_patterns = Some(s),
+            None => output.exclude_patterns = b.exclude_patterns
+        }
+
+        match a.page_size {
+            Some(s) => output.page_size = Some(s),
+            None => output.page_size = b.page_size
+        }
+
+        match a.max_attempts {
+            Some(s) => output.max_attempts = Some(s),
+            None => output.max_attempts = b.max_attempts
+        }
+
+        match a.service_account_key {
+            Some(s) => output.service_account_key = Some(s),
+            None => output.service_account_key = b.service_account_key
+        }
+
+        match a.root_folder {
+            Some(s) => output.root_folder = Some(s),
+            None => output.root_folder = b.root_folder
+        }
+
         output
     }
 
-    /// Get the current configuration from the database
+    /// Get the current configuration of the profile of `env` from the database
     ///
     /// ## Error
     /// - When a database operation fails
     pub fn get_config(env: &Env) -> Result<Self> {
         let conn = unwrap_db_err!(env.get_conn());
 
-        let mut stmt = unwrap_db_err!(conn.prepare("SELECT * FROM config"));
-        let mut result = unwrap_db_err!(stmt.query(named_params! {}));
+        let mut stmt = unwrap_db_err!(conn.prepare("SELECT * FROM config WHERE profile = :profile"));
+        let mut result = unwrap_db_err!(stmt.query(named_params! {
+            ":profile": &env.profile
+        }));
 
         match result.next() {
             Ok(Some(row)) => {
@@ -94,28 +149,43 @@ impl Configuration {
                 let client_secret = unwrap_db_err!(row.get::<&str, Option<String>>("client_secret"));
                 let input_files = unwrap_db_err!(row.get::<&str, Option<String>>("input_files"));
                 let drive_id = unwrap_db_err!(row.get::<&str, Option<String>>("drive_id"));
-
-                Ok(Self { client_id, client_secret, input_files, drive_id })
+                let include_patterns = unwrap_db_err!(row.get::<&str, Option<String>>("include_patterns"));
+                let exclude_patterns = unwrap_db_err!(row.get::<&str, Option<String>>("exclude_patterns"));
+                let page_size = unwrap_db_err!(row.get::<&str, Option<u32>>("page_size"));
+                let max_attempts = unwrap_db_err!(row.get::<&str, Option<u32>>("max_attempts"));
+                let service_account_key = unwrap_db_err!(row.get::<&str, Option<String>>("service_account_key"));
+                let root_folder = unwrap_db_err!(row.get::<&str, Option<String>>("root_folder"));
+
+                Ok(Self { client_id, client_secret, input_files, drive_id, include_patterns, exclude_patterns, page_size, max_attempts, service_account_key, root_folder })
             },
             Ok(None) => Ok(Self::empty()),
-            Err(e) => Err((Error::DatabaseError(e), line!(), file!()))
+            Err(e) => Err(new_err!(ErrorKind::Database(e)))
         }
     }
 
-    /// Write the current configuration to the database
+    /// Write the current configuration to the database, as the configuration of the profile of `env`
     ///
     /// ## Error
     /// - When a database operation fails
     pub fn write(&self, env: &Env) -> Result<()> {
         let conn = unwrap_db_err!(env.get_conn());
 
-        unwrap_db_err!(conn.execute("DELETE FROM config", named_params! {}));
+        unwrap_db_err!(conn.execute("DELETE FROM config WHERE profile = :profile", named_params! {
+            ":profile": &env.profile
+        }));
 
-        unwrap_db_err!(conn.execute("INSERT INTO config (client_id, client_secret, input_files, drive_id) VALUES (:client_id, :client_secret, :input_files, :drive_id)", named_params! {
+        unwrap_db_err!(conn.execute("INSERT INTO config (client_id, client_secret, input_files, drive_id, include_patterns, exclude_patterns, page_size, max_attempts, service_account_key, root_folder, profile) VALUES (:client_id, :client_secret, :input_files, :drive_id, :include_patterns, :exclude_patterns, :page_size, :max_attempts, :service_account_key, :root_folder, :profile)", named_params! {
             ":client_id":       &self.client_id,
             ":client_secret":   &self.client_secret,
             ":input_files":     &self.input_files,
-            ":drive_id":         &self.drive_id
+            ":drive_id":         &self.drive_id,
+            ":include_patterns": &self.include_patterns,
+            ":exclude_patterns": &self.exclude_patterns,
+            ":page_size":        &self.page_size,
+            ":max_attempts":     &self.max_attempts,
+            ":service_account_key":  &self.service_account_key,
+            ":root_folder":      &self.root_folder,
+            ":profile":          &env.profile
         }));
 
         Ok(())
@@ -18,9 +18,30 @@ pub struct Env {
     pub drive_id:       Option<String>,
 
     /// The ID of the root folder ('GSync')
-    pub root_folder:    String
+    pub root_folder:    String,
+
+    /// The name of the root folder in Google Drive
+    pub root_folder_name:   String,
+
+    /// The profile whose configuration and login are used
+    pub profile:        String,
+
+    /// The number of results to request per page when listing files in Google Drive. Google's maximum is used if not set
+    pub page_size:      Option<u32>,
+
+    /// How often a request to Google is attempted before giving up. A default is used if not set
+    pub max_attempts:   Option<u32>,
+
+    /// The path to the JSON key of the service account to authenticate with. The logged in user is used if not set
+    pub service_account_key:    Option<String>
 }
 
+/// The profile used when no profile is given
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// The name of the root folder in Google Drive, if no other name is configured
+pub const DEFAULT_ROOT_FOLDER_NAME: &str = "GSync";
+
 #[cfg(unix)]
 /// Unix path to the gsync home folder
 const DB_PATH: &str = "%home%/.gsync/";
@@ -44,7 +65,12 @@ impl Env {
             client_secret:  secret.as_ref().to_string(),
             client_id:      id.as_ref().to_string(),
             drive_id:       drive_id.map(|id| id.as_ref().to_string()),
-            root_folder:    root_folder.as_ref().to_string()
+            root_folder:    root_folder.as_ref().to_string(),
+            root_folder_name:   DEFAULT_ROOT_FOLDER_NAME.to_string(),
+            profile:        DEFAULT_PROFILE.to_string(),
+            page_size:      None,
+            max_attempts:   None,
+            service_account_key:    None
         }
     }
 
@@ -62,16 +88,23 @@ impl Env {
             client_id:      String::new(),
             client_secret:  String::new(),
             drive_id:       None,
-            root_folder:    String::new()
+            root_folder:    String::new(),
+            root_folder_name:   DEFAULT_ROOT_FOLDER_NAME.to_string(),
+            profile:        DEFAULT_PROFILE.to_string(),
+            page_size:      None,
+            max_attempts:   None,
+            service_account_key:    None
         }
     }
 
-    /// Get a connection to the database
+    /// Get a connection to the database. When syncing concurrently, a connection waits for other connections to release their lock
     pub fn get_conn(&self) -> Result<rusqlite::Connection, rusqlite::Error> {
         let mut path = std::path::PathBuf::from(&self.db);
         path.push("data.db3");
 
-        rusqlite::Connection::open(path.as_path())
+        let conn = rusqlite::Connection::open(path.as_path())?;
+        conn.busy_timeout(std::time::Duration::from_secs(30))?;
+        Ok(conn)
     }
 }
 
@@ -0,0 +1,201 @@
+//! The error type of GSync
+
+use crate::api::GoogleError;
+use reqwest::StatusCode;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Type alias for Result
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Struct describing an error which occurred in GSync: what went wrong, what GSync was working on, and where in the source code it happened
+#[derive(Debug)]
+pub struct Error {
+    /// What went wrong
+    kind:       ErrorKind,
+
+    /// What GSync was working on, innermost first
+    context:    Vec<Context>,
+
+    /// The line at which the error occurred
+    line:       u32,
+
+    /// The source file in which the error occurred
+    file:       &'static str
+}
+
+/// Enum describing the kinds of errors which can often occur in GSync
+#[derive(Debug)]
+pub enum ErrorKind {
+    /// Error returned by the Google API
+    Google(GoogleError),
+
+    /// Error resulting from a database operation
+    Database(rusqlite::Error),
+
+    /// Error resulting from a reqwest operation
+    Request(reqwest::Error),
+
+    /// Error resulting from an IO operation
+    Io(std::io::Error),
+
+    /// Google responded with an unexpected HTTP status, without an error GSync could read
+    Status(StatusCode),
+
+    /// An error which does not fit in any other category
+    Other(String)
+}
+
+/// Enum describing what GSync was working on when an error occurred
+#[derive(Debug, PartialEq)]
+pub enum Context {
+    /// The local path of the file or directory
+    Path(PathBuf),
+
+    /// The ID of the file or folder in Google Drive
+    FileId(String)
+}
+
+impl Error {
+    /// Create an error without context. Use the `new_err!` macro to fill in the location
+    pub fn new(kind: ErrorKind, line: u32, file: &'static str) -> Self {
+        Self {
+            kind,
+            context:    Vec::new(),
+            line,
+            file
+        }
+    }
+
+    /// What went wrong
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    /// What GSync was working on, innermost first
+    pub fn context(&self) -> &[Context] {
+        &self.context
+    }
+
+    /// The HTTP status of the response which caused this error, if it was caused by a response
+    pub fn status(&self) -> Option<StatusCode> {
+        match &self.kind {
+            ErrorKind::Google(e) => StatusCode::from_u16(e.code as u16).ok(),
+            ErrorKind::Request(e) => e.status(),
+            ErrorKind::Status(status) => Some(*status),
+            _ => None
+        }
+    }
+
+    /// Describe the error together with everything which caused it, on a single line
+    pub fn report(&self) -> String {
+        let mut report = self.to_string();
+        let mut source = std::error::Error::source(self);
+        while let Some(cause) = source {
+            report.push_str(&format!(": {}", cause));
+            source = cause.source();
+        }
+
+        report
+    }
+
+    /// Add context, unless the error already has context of the same kind. The innermost context is the most specific
+    fn with(mut self, context: Context) -> Self {
+        if !self.context.iter().any(|c| std::mem::discriminant(c) == std::mem::discriminant(&context)) {
+            self.context.push(context);
+        }
+
+        self
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (", self.kind)?;
+        for context in &self.context {
+            write!(f, "{}, ", context)?;
+        }
+
+        write!(f, "line {} in {})", self.line, self.file)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            ErrorKind::Google(e) => Some(e),
+            ErrorKind::Database(e) => Some(e),
+            ErrorKind::Request(e) => Some(e),
+            ErrorKind::Io(e) => Some(e),
+            ErrorKind::Status(_) | ErrorKind::Other(_) => None
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Google(_) => write!(f, "The Google API returned an error"),
+            Self::Database(_) => write!(f, "An error occurred while processing or handling database data"),
+            Self::Request(_) => write!(f, "An error occurred while sending a HTTP request"),
+            Self::Io(_) => write!(f, "An IO operation failed"),
+            Self::Status(status) => write!(f, "Google responded with unexpected status {}", status),
+            Self::Other(message) => write!(f, "{}", message)
+        }
+    }
+}
+
+impl fmt::Display for Context {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Path(path) => write!(f, "path '{}'", path.to_string_lossy()),
+            Self::FileId(id) => write!(f, "Drive file '{}'", id)
+        }
+    }
+}
+
+/// Trait for adding context to the error of a Result
+pub trait ResultExt<T> {
+    /// Record the local path GSync was working on when the error occurred
+    ///
+    /// # Errors
+    /// - The error of `self`, with the path added
+    fn with_path<P: AsRef<Path>>(self, path: P) -> Result<T>;
+
+    /// Record the ID of the file in Google Drive GSync was working on when the error occurred
+    ///
+    /// # Errors
+    /// - The error of `self`, with the file ID added
+    fn with_file_id(self, id: &str) -> Result<T>;
+}
+
+impl<T> ResultExt<T> for Result<T> {
+    fn with_path<P: AsRef<Path>>(self, path: P) -> Result<T> {
+        self.map_err(|e| e.with(Context::Path(path.as_ref().to_path_buf())))
+    }
+
+    fn with_file_id(self, id: &str) -> Result<T> {
+        self.map_err(|e| e.with(Context::FileId(id.to_string())))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Error, ErrorKind, Context, ResultExt, Result};
+    use std::path::PathBuf;
+
+    #[test]
+    fn innermost_context_is_kept() {
+        let result: Result<()> = Err(Error::new(ErrorKind::Other("Failed".to_string()), 1, "src/error.rs"));
+        let e = result.with_path("a/b").with_file_id("id").with_path("a").unwrap_err();
+
+        assert_eq!(&[Context::Path(PathBuf::from("a/b")), Context::FileId("id".to_string())], e.context());
+        assert_eq!("Failed (path 'a/b', Drive file 'id', line 1 in src/error.rs)", e.to_string());
+    }
+
+    #[test]
+    fn report_includes_the_cause() {
+        let e = Error::new(ErrorKind::Io(std::io::Error::new(std::io::ErrorKind::NotFound, "gone")), 2, "src/error.rs");
+        assert_eq!("An IO operation failed (line 2 in src/error.rs): gone", e.report());
+    }
+}
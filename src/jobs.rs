@@ -0,0 +1,203 @@
+//! Module describing sync jobs: local directories which are synced to their own destination in Google Drive
+
+use crate::backend::Backend;
+use crate::config::Configuration;
+use crate::env::Env;
+use rusqlite::named_params;
+use crate::{Result, unwrap_db_err};
+
+/// Struct describing a sync job. The destination folder stands for the source directory, i.e. the contents of the source are synced into it
+#[derive(Debug)]
+pub struct SyncJob {
+    /// The name of the job, unique within a profile
+    pub name:               String,
+
+    /// The absolute path of the local directory to sync
+    pub source:             String,
+
+    /// The path of the destination folder, relative to the root of 'My Drive' or of the shared drive, e.g. `Backups/Notes`
+    pub destination:        Option<String>,
+
+    /// The ID of the destination folder, used instead of `destination`
+    pub destination_id:     Option<String>,
+
+    /// If syncing to a Team Drive/Shared Drive, the ID of that drive. The drive of the configuration isn't used for jobs
+    pub drive_id:           Option<String>,
+
+    /// Gitignore patterns of files to sync even if they are ignored, comma seperated
+    pub include_patterns:   Option<String>,
+
+    /// Gitignore patterns of files to never sync, comma seperated
+    pub exclude_patterns:   Option<String>
+}
+
+impl SyncJob {
+
+    /// Check if the job can be run. The destination has to be a folder, since anything else in the root of the drive would be removed by `gsync sync --delete`
+    pub fn is_valid(&self) -> (bool, &str) {
+        let destination_is_root = match (&self.destination, &self.destination_id) {
+            (Some(destination), _) => destination.split('/').all(str::is_empty),
+            (None, Some(id)) => id == "root" || self.drive_id.as_ref() == Some(id),
+            (None, None) => return (false, "the destination is empty")
+        };
+
+        if destination_is_root {
+            (false, "the destination must be a folder, not the root of the drive")
+        } else if self.source.contains(',') {
+            (false, "the source may not contain a ','")
+        } else {
+            (true, "")
+        }
+    }
+
+    /// The configuration to sync this job with: its source and ignore rules
+    pub fn config(&self) -> Configuration {
+        Configuration {
+            input_files:        Some(self.source.clone()),
+            include_patterns:   self.include_patterns.clone(),
+            exclude_patterns:   self.exclude_patterns.clone(),
+            ..Configuration::empty()
+        }
+    }
+
+    /// Describe where the job syncs to
+    pub fn describe_destination(&self) -> String {
+        let drive = match &self.drive_id {
+            Some(drive_id) => format!("shared drive '{}'", drive_id),
+            None => "My Drive".to_string()
+        };
+
+        match (&self.destination, &self.destination_id) {
+            (Some(destination), _) => format!("'{}' in {}", destination, drive),
+            (None, Some(id)) => format!("folder '{}' in {}", id, drive),
+            (None, None) => drive
+        }
+    }
+
+    /// Find the ID of the destination folder. Folders on the destination path which don't exist yet are created if `create` is true,
+    /// otherwise `None` is returned
+    ///
+    /// ## Error
+    /// - When a request to Google fails
+    pub async fn find_destination(&self, backend: &dyn Backend, create: bool) -> Result<Option<String>> {
+        let destination = match (&self.destination, &self.destination_id) {
+            (Some(destination), _) => destination,
+            (None, id) => return Ok(id.clone())
+        };
+
+        let mut parent = self.drive_id.clone().unwrap_or_else(|| "root".to_string());
+        for name in destination.split('/').filter(|name| !name.is_empty()) {
+            parent = match backend.find(&parent, name, true).await?.into_iter().next() {
+                Some(folder) => folder.id,
+                None if create => {
+                    println!("Info: Creating destination folder '{}'", name);
+                    backend.create_folder(name, &parent).await?
+                },
+                None => return Ok(None)
+            };
+        }
+
+        Ok(Some(parent))
+    }
+
+    /// Get all sync jobs of the profile of `env`, ordered by name
+    ///
+    /// ## Error
+    /// - When a database operation fails
+    pub fn list(env: &Env) -> Result<Vec<Self>> {
+        let conn = unwrap_db_err!(env.get_conn());
+
+        let mut stmt = unwrap_db_err!(conn.prepare("SELECT * FROM sync_jobs WHERE profile = :profile ORDER BY name"));
+        let mut result = unwrap_db_err!(stmt.query(named_params! {
+            ":profile": &env.profile
+        }));
+
+        let mut jobs = Vec::new();
+        while let Some(row) = unwrap_db_err!(result.next()) {
+            jobs.push(Self {
+                name:               unwrap_db_err!(row.get("name")),
+                source:             unwrap_db_err!(row.get("source")),
+                destination:        unwrap_db_err!(row.get("destination")),
+                destination_id:     unwrap_db_err!(row.get("destination_id")),
+                drive_id:           unwrap_db_err!(row.get("drive_id")),
+                include_patterns:   unwrap_db_err!(row.get("include_patterns")),
+                exclude_patterns:   unwrap_db_err!(row.get("exclude_patterns"))
+            });
+        }
+
+        Ok(jobs)
+    }
+
+    /// Get the sync job of the profile of `env` with the given name
+    ///
+    /// ## Error
+    /// - When a database operation fails
+    pub fn get(env: &Env, name: &str) -> Result<Option<Self>> {
+        Ok(Self::list(env)?.into_iter().find(|job| job.name == name))
+    }
+
+    /// Write the job to the database, as a job of the profile of `env`. A job with the same name is replaced
+    ///
+    /// ## Error
+    /// - When a database operation fails
+    pub fn write(&self, env: &Env) -> Result<()> {
+        let conn = unwrap_db_err!(env.get_conn());
+
+        unwrap_db_err!(conn.execute("INSERT OR REPLACE INTO sync_jobs (profile, name, source, destination, destination_id, drive_id, include_patterns, exclude_patterns) VALUES (:profile, :name, :source, :destination, :destination_id, :drive_id, :include_patterns, :exclude_patterns)", named_params! {
+            ":profile":          &env.profile,
+            ":name":             &self.name,
+            ":source":           &self.source,
+            ":destination":      &self.destination,
+            ":destination_id":   &self.destination_id,
+            ":drive_id":         &self.drive_id,
+            ":include_patterns": &self.include_patterns,
+            ":exclude_patterns": &self.exclude_patterns
+        }));
+
+        Ok(())
+    }
+
+    /// Remove the sync job of the profile of `env` with the given name. Returns whether such a job existed
+    ///
+    /// ## Error
+    /// - When a database operation fails
+    pub fn delete(env: &Env, name: &str) -> Result<bool> {
+        let conn = unwrap_db_err!(env.get_conn());
+
+        let removed = unwrap_db_err!(conn.execute("DELETE FROM sync_jobs WHERE profile = :profile AND name = :name", named_params! {
+            ":profile": &env.profile,
+            ":name":    name
+        }));
+
+        Ok(removed > 0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SyncJob;
+
+    /// A job syncing `/home/user/notes` to the given destination
+    fn job(destination: Option<&str>, destination_id: Option<&str>, drive_id: Option<&str>) -> SyncJob {
+        SyncJob {
+            name:               "notes".to_string(),
+            source:             "/home/user/notes".to_string(),
+            destination:        destination.map(str::to_string),
+            destination_id:     destination_id.map(str::to_string),
+            drive_id:           drive_id.map(str::to_string),
+            include_patterns:   None,
+            exclude_patterns:   None
+        }
+    }
+
+    #[test]
+    fn destination_must_be_a_folder() {
+        assert!(job(Some("Notes"), None, None).is_valid().0);
+        assert!(job(None, Some("folder-id"), Some("drive-id")).is_valid().0);
+
+        assert!(!job(Some("/"), None, None).is_valid().0);
+        assert!(!job(None, Some("root"), None).is_valid().0);
+        assert!(!job(None, Some("drive-id"), Some("drive-id")).is_valid().0);
+        assert!(!job(None, None, None).is_valid().0);
+    }
+}
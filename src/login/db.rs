@@ -5,7 +5,7 @@ use rusqlite::named_params;
 use crate::api::oauth::LoginData;
 use crate::{Result, unwrap_db_err};
 
-/// Save login data to the database
+/// Save login data to the database, as the login of the profile of `env`
 ///
 /// ## Errors
 /// - When a database operation fails
@@ -13,22 +13,42 @@ pub fn save_to_database(login_data: &LoginData, env: &Env) -> Result<()> {
     let conn = unwrap_db_err!(env.get_conn());
 
     if login_data.refresh_token.is_some() {
-        unwrap_db_err!(conn.execute("DELETE FROM user", named_params! {}));
+        unwrap_db_err!(conn.execute("DELETE FROM user WHERE profile = :profile", named_params! {
+            ":profile": &env.profile
+        }));
     }
 
     let expiry_time = chrono::Utc::now().timestamp() + login_data.expires_in;
-    unwrap_db_err!(if login_data.refresh_token.is_some() {
-            conn.execute("INSERT INTO user (refresh_token, access_token, expiry) VALUES (:refresh_token, :access_token, :expiry)", named_params! {
-                ":refresh_token": &login_data.refresh_token.as_ref().unwrap(),
+    unwrap_db_err!(if let Some(refresh_token) = &login_data.refresh_token {
+            conn.execute("INSERT INTO user (refresh_token, access_token, expiry, profile) VALUES (:refresh_token, :access_token, :expiry, :profile)", named_params! {
+                ":refresh_token": refresh_token,
                 ":access_token": &login_data.access_token,
-                ":expiry": expiry_time
+                ":expiry": expiry_time,
+                ":profile": &env.profile
             })
         } else {
-            conn.execute("UPDATE user SET access_token = :access_token, expiry = :expiry", named_params! {
+            conn.execute("UPDATE user SET access_token = :access_token, expiry = :expiry WHERE profile = :profile", named_params! {
                 ":access_token": &login_data.access_token,
-                ":expiry": expiry_time
+                ":expiry": expiry_time,
+                ":profile": &env.profile
             })
         });
 
+    Ok(())
+}
+
+/// Forget the user logged in with the profile of `env`, together with everything the profile stored about the files in Drive:
+/// the synced files, the change tokens, and the sessions of interrupted uploads. Other profiles keep theirs
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn delete_from_database(env: &Env) -> Result<()> {
+    let conn = unwrap_db_err!(env.get_conn());
+    for table in &["user", "sync_state", "change_tokens", "upload_sessions"] {
+        unwrap_db_err!(conn.execute(&format!("DELETE FROM {} WHERE profile = :profile", table), named_params! {
+            ":profile": &env.profile
+        }));
+    }
+
     Ok(())
 }
\ No newline at end of file
@@ -7,10 +7,28 @@ pub mod db;
 use crate::env::Env;
 use actix_web::{HttpServer, App};
 use rand::Rng;
-use std::sync::mpsc::{Sender, channel};
-use crate::api::oauth::LoginData;
+use serde::Deserialize;
+use tokio::sync::{mpsc, oneshot};
+use crate::api::oauth::{LoginData, DevicePoll};
 
-use crate::{Result, unwrap_other_err};
+use crate::{Result, ErrorKind, unwrap_other_err, unwrap_io_err, new_err};
+
+/// The redirect URI used when logging in without a browser on this machine. Nothing listens on port 1, so the browser shows an error page
+/// after logging in, with the authorization code in its address bar
+const HEADLESS_REDIRECT_URI: &str = "http://localhost:1";
+
+/// Struct describing the query parameters of the URL Google redirects to after logging in
+#[derive(Deserialize)]
+struct RedirectQuery {
+    /// The authorization code
+    code:   Option<String>,
+
+    /// The error, if logging in failed
+    error:  Option<String>,
+
+    /// The state parameter which was given to Google in the authentication URL
+    state:  Option<String>
+}
 
 /// Struct describing the data to be passed to Actix endpoints
 #[derive(Clone, Debug)]
@@ -19,11 +37,11 @@ pub struct ActixData {
     state:          String,
 
     /// THe channel on which the endpoint can send the received code
-    tx:             Sender<String>
+    tx:             mpsc::UnboundedSender<String>
 }
 
 /// Perform the OAuth2 login flow
-pub fn perform_oauth2_login(env: &Env) -> Result<LoginData> {
+pub async fn perform_oauth2_login(env: &Env, http: &reqwest::Client) -> Result<LoginData> {
     //Generate a code_verifier and code_challenge
     let (code_verifier, code_challenge) = generate_code();
     //Generate a state parameter
@@ -40,46 +58,141 @@ pub fn perform_oauth2_login(env: &Env) -> Result<LoginData> {
     };
 
     //This channel will be used to receive the code from the HTTP endpoint
-    let (tx_code, rx_code) = channel();
+    let (tx_code, mut rx_code) = mpsc::unbounded_channel();
     let actix_data = ActixData { state: state.clone(), tx: tx_code};
 
     //This channel will be used to receive the Serve instance from Actix
-    let (tx_srv, rx_srv) = channel();
+    let (tx_srv, rx_srv) = oneshot::channel();
 
-    //Start the actix web server and wait for it to return us the Server instance
+    //Start the actix web server on a thread of its own, as it runs its own System, and wait for it to return us the Server instance
     std::thread::spawn(move || {
         match start_actix(actix_data, port, tx_srv) {
             Ok(_) => {},
             Err(e) => {
-                eprintln!("Error: Failed to start Actix Web Server: {:?}", e);
+                eprintln!("Error: Failed to start Actix Web Server: {}", e.report());
                 std::process::exit(1);
             }
         }
     });
-    let server = unwrap_other_err!(rx_srv.recv());
+    let server = unwrap_other_err!(rx_srv.await);
 
-    let auth_uri = crate::api::oauth::create_authentication_uri(&env, &code_challenge, &state, &format!("http://localhost:{}", port));
+    let auth_uri = crate::api::oauth::create_authentication_uri(env, &code_challenge, &state, &format!("http://localhost:{}", port));
 
     println!("Info: Please open the following URL:");
     println!("\n{}\n", auth_uri);
 
     //Wait for the code from the HTTP endpoint
-    let code = unwrap_other_err!(rx_code.recv());
+    let code = match rx_code.recv().await {
+        Some(code) => code,
+        None => return Err(new_err!(ErrorKind::Other("The Actix Web Server stopped before a code was received".to_string())))
+    };
 
     println!("Info: Code received. Exchanging for tokens.");
 
     //Stop the Actix web server, we dont need it anymore
-    actix_web::rt::System::new("").block_on(server.stop(true));
+    server.stop(true).await;
+
+    crate::api::oauth::exchange_access_token(env, http, &code, &code_verifier, &format!("http://localhost:{}", port)).await
+}
+
+/// Perform the OAuth2 login flow without a browser on this machine, e.g. over SSH. The user opens the authentication URL on any machine,
+/// and pastes the authorization code, or the URL the browser was redirected to, on stdin
+///
+/// ## Errors
+/// - When reading from stdin fails
+/// - When the pasted input contains an error, or doesn't match the login
+/// - When exchanging the code for tokens fails
+pub async fn perform_headless_login(env: &Env, http: &reqwest::Client) -> Result<LoginData> {
+    let (code_verifier, code_challenge) = generate_code();
+    let state = rand::thread_rng().sample_iter(rand::distributions::Alphanumeric).take(32).map(char::from).collect::<String>();
+
+    let auth_uri = crate::api::oauth::create_authentication_uri(env, &code_challenge, &state, HEADLESS_REDIRECT_URI);
+
+    println!("Info: Please open the following URL in a browser on any machine:");
+    println!("\n{}\n", auth_uri);
+    println!("Info: After logging in, the browser is redirected to a page which fails to load. Paste the URL of that page, or the code in it, below:");
+
+    let mut input = String::new();
+    unwrap_io_err!(std::io::stdin().read_line(&mut input));
+    let code = parse_pasted_code(&input, &state)?;
 
-    crate::api::oauth::exchange_access_token(&env, &code, &code_verifier, &format!("http://localhost:{}", port))
+    println!("Info: Code received. Exchanging for tokens.");
+    crate::api::oauth::exchange_access_token(env, http, &code, &code_verifier, HEADLESS_REDIRECT_URI).await
+}
+
+/// Perform the OAuth2 device flow: the user enters a code on another device, e.g. a phone, while GSync polls Google until they did.
+/// Needs neither a browser on this machine nor a port the browser can reach
+///
+/// ## Errors
+/// - When requesting the device code fails
+/// - When the user denied access, or didn't enter the code before it expired
+/// - When polling for the tokens fails
+pub async fn perform_device_login(env: &Env, http: &reqwest::Client) -> Result<LoginData> {
+    let device_code = crate::api::oauth::request_device_code(env, http).await?;
+
+    println!("Info: Please open {} on any device and enter the code:", device_code.verification_url);
+    println!("\n{}\n", device_code.user_code);
+    println!("Info: Waiting for the code to be entered.");
+
+    let expiry = chrono::Utc::now().timestamp() + device_code.expires_in;
+    let mut interval = device_code.interval;
+    loop {
+        tokio::time::sleep(std::time::Duration::from_secs(interval)).await;
+        if chrono::Utc::now().timestamp() > expiry {
+            return Err(new_err!(ErrorKind::Other("The code expired before it was entered. Run `gsync login --device` again".to_string())));
+        }
+
+        match crate::api::oauth::poll_device_token(env, http, &device_code.device_code).await? {
+            DevicePoll::Pending => {},
+            // Google asks to wait 5 seconds longer between polls
+            DevicePoll::SlowDown => interval += 5,
+            DevicePoll::Done(login_data) => {
+                println!("Info: Code entered.");
+                return Ok(login_data);
+            }
+        }
+    }
+}
+
+/// Get the authorization code from what the user pasted: either the code itself, or the URL Google redirected to
+///
+/// ## Errors
+/// - When nothing was pasted
+/// - When the URL contains an error, no code, or a state which doesn't match `state`
+fn parse_pasted_code(input: &str, state: &str) -> Result<String> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err(new_err!(ErrorKind::Other("No authorization code was entered".to_string())));
+    }
+
+    let query = match input.split_once('?') {
+        Some((_, query)) => query,
+        None if input.contains("code=") => input,
+        // Just the code
+        None => return Ok(input.to_string())
+    };
+
+    let query: RedirectQuery = unwrap_other_err!(serde_qs::from_str(query.split('#').next().unwrap_or_default()));
+    if let Some(error) = query.error {
+        return Err(new_err!(ErrorKind::Other(format!("Logging in failed: {}", error))));
+    }
+
+    if query.state.as_deref() != Some(state) {
+        return Err(new_err!(ErrorKind::Other("The pasted URL doesn't belong to this login attempt".to_string())));
+    }
+
+    match query.code {
+        Some(code) => Ok(code),
+        None => Err(new_err!(ErrorKind::Other("The pasted URL doesn't contain an authorization code".to_string())))
+    }
 }
 
 /// Start the Actix Web Server.
 /// This is a blocking method call
 /// An instance of Actix's Server will be send over the provided channel so it can be stopped later
-fn start_actix(data: ActixData, port: u16, tx: Sender<actix_server::Server>) -> Result<()> {
+fn start_actix(data: ActixData, port: u16, tx: oneshot::Sender<actix_server::Server>) -> Result<()> {
     let mut sys = actix_web::rt::System::new("GSync");
-    let actix = unwrap_other_err!(HttpServer::new(move || {
+    let actix = unwrap_io_err!(HttpServer::new(move || {
         App::new()
             .data(data.clone())
             .service(callback_endpoint::authorization)
@@ -110,4 +223,18 @@ fn generate_code() -> (String, String) {
 
         return (code_verifier, code_challenge.replace("=", ""))
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_pasted_code;
+
+    #[test]
+    fn pasted_code() {
+        assert_eq!("4/0Abc-d_e", parse_pasted_code(" 4/0Abc-d_e\n", "state").unwrap());
+        assert_eq!("4/0Abc", parse_pasted_code("http://localhost:1/?state=state&code=4%2F0Abc&scope=https://www.googleapis.com/auth/drive", "state").unwrap());
+        assert!(parse_pasted_code("http://localhost:1/?state=other&code=4%2F0Abc", "state").is_err());
+        assert!(parse_pasted_code("http://localhost:1/?error=access_denied&state=state", "state").is_err());
+        assert!(parse_pasted_code("\n", "state").is_err());
+    }
+}
@@ -8,7 +8,7 @@ macro_rules! unwrap_db_err {
     ($expression:expr) => {
         match $expression {
             Ok(t) => t,
-            Err(e) => return Err(($crate::Error::DatabaseError(e), std::line!(), std::file!()))
+            Err(e) => return Err($crate::new_err!($crate::ErrorKind::Database(e)))
         }
     }
 }
@@ -21,7 +21,20 @@ macro_rules! unwrap_req_err {
     ($expression:expr) => {
         match $expression {
             Ok(t) => t,
-            Err(e) => return Err(($crate::Error::RequestError(e), std::line!(), std::file!()))
+            Err(e) => return Err($crate::new_err!($crate::ErrorKind::Request(e)))
+        }
+    }
+}
+
+/// Macro for handling errors returned from IO operations
+///
+/// The argument of this macro invocation should be a `Result<T, std::io::Error>`
+#[macro_export]
+macro_rules! unwrap_io_err {
+    ($expression:expr) => {
+        match $expression {
+            Ok(t) => t,
+            Err(e) => return Err($crate::new_err!($crate::ErrorKind::Io(e)))
         }
     }
 }
@@ -34,29 +47,31 @@ macro_rules! unwrap_other_err {
     ($expression:expr) => {
         match $expression {
             Ok(t) => t,
-            Err(e) => return Err(($crate::Error::Other(e.to_string()), std::line!(), std::file!()))
+            Err(e) => return Err($crate::new_err!($crate::ErrorKind::Other(e.to_string())))
         }
     }
 }
 
+/// Create a `crate::Error` of the given `crate::ErrorKind`, which occurred at the location of the macro invocation
+#[macro_export]
+macro_rules! new_err {
+    ($kind:expr) => {
+        $crate::Error::new($kind, std::line!(), std::file!())
+    }
+}
+
 /// Handle a Result<T, crate::Error>
 ///
 /// When the passed in Result is `Ok`, this macro will return `T`.
-/// When the passed in Result is `Err`, this macro will print out the Error in a nice way to stderr and exit with exit code 1
+/// When the passed in Result is `Err`, this macro will print out the Error and its causes to stderr and exit with exit code 1
 ///
 #[macro_export]
 macro_rules! handle_err {
     ($expression:expr) => {
         match $expression {
             Ok(t) => t,
-            Err((e, line, file)) => {
-                match e {
-                    $crate::Error::DatabaseError(e) => eprintln!("Error: An error occurred while processing or handling database data: {:?} (line {} in {})", e, line, file),
-                    $crate::Error::RequestError(e) => eprintln!("Error: An error occurred while sending a HTTP request: {:?} (line {} in {})", e, line, file),
-                    $crate::Error::GoogleError(e) => eprintln!("Error: The Google API returned an error: {:?} (line {} in {})", e, line, file),
-                    $crate::Error::Other(e) => eprintln!("Error: An error occurred: {:?} (line {} in {})", e, line, file)
-                }
-
+            Err(e) => {
+                eprintln!("Error: {}", e.report());
                 eprintln!("This is a fatal error. Exiting!");
                 std::process::exit(1);
             }
@@ -110,10 +125,9 @@ macro_rules! handle_err {
 #[macro_export]
 macro_rules! unwrap_google_err {
     ($expression:expr) => {
-        if $expression.error.is_some() {
-            return Err(($crate::Error::GoogleError($expression.error.unwrap()), std::line!(), std::file!()));
-        } else {
-            $expression.data.unwrap()
+        match $expression.error {
+            Some(e) => return Err($crate::new_err!($crate::ErrorKind::Google(e))),
+            None => $expression.data.unwrap()
         }
     }
 }
\ No newline at end of file
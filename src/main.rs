@@ -13,11 +13,41 @@
 //! 3. Enable the Google Drive API
 //! 4. If you are planning to use a Team Drive/Shared Drive, run `gsync drives` to get the ID of the drive you want to sync to
 //! 5. Configure GSync: `gsync config -i <GOOGLE APP ID> -s <GOOGLE APP SECRET> -f <INPUT FILES> -d <ID OF SHARED DRIVE>`. The `-d` parameter is optional
-//! 6. Login: `gsync login`
+//! 6. Login: `gsync login`. On a machine without a browser, e.g. over SSH, run `gsync login --no-browser`, open the printed URL on any machine, and paste the URL you end up at, or the code in it. Alternatively, run `gsync login --device` and enter the printed code at the printed URL on any device, e.g. your phone. Google only allows this with OAuth2 credentials of type 'TVs and Limited Input devices', credentials of type 'Desktop app' are refused with `invalid_client`. To log in this way, create credentials of that type in step 2 and configure those in step 5. They can only be used with `--device`. When logging in this way, Google only gives GSync access to the files it created itself, so files added to the GSync folder in other ways aren't seen
 //! 7. Sync away! `gsync sync`
 //!
 //! To update your configuration later, run `gsync config` again, you don't have to re-provide all options if you don't want to change them
 //!
+//! Run `gsync whoami` to see which Google account GSync is logged in with. `gsync logout` revokes the login with Google, and makes GSync forget what it knows about the files in that account's Drive, so you can log in with another account
+//!
+//! To sync to more than one Google account or destination from the same machine, use profiles. Every profile has its own configuration and login, e.g. `gsync --profile work config ...`, `gsync --profile work login` and `gsync --profile work sync`. Without `--profile`, the profile `default` is used. Profiles syncing to the same Drive should each get their own root folder with `gsync config --root-folder <NAME>`, instead of the default `GSync`
+//!
+//! Sync jobs send a local directory to a folder of its own, instead of into the `GSync` folder. For example, `gsync job add notes --source ~/notes --to Notes` syncs the contents of `~/notes` into the folder `Notes` in My Drive, and `gsync job add projects --source ~/projects --to Projects -d <ID OF SHARED DRIVE> --exclude target/` syncs `~/projects` into `Projects` on a shared drive. Missing destination folders are created. Use `--to-id <ID>` to give the destination folder by its ID. `gsync sync` syncs the configured files followed by every job, and `gsync sync <JOB>` runs a single job. When only jobs are used, `-f` can be left out of `gsync config`. See the jobs with `gsync job list`, and remove one with `gsync job remove <JOB>`; nothing is removed from Google Drive then
+//!
+//! Folders are listed 1000 entries at a time. If requests time out on a slow connection, a smaller page size can be set with `gsync config --page-size <SIZE>`
+//!
+//! When Google rate limits GSync or fails temporarily, the request is retried with an increasing delay, or after the delay Google asks for. A request is attempted 5 times before GSync gives up, this can be changed with `gsync config --max-attempts <N>`
+//!
+//! To sync without a person's login, e.g. for backups of CI artifacts to a shared drive, GSync can authenticate as a Google service account. Create a JSON key for the service account in the Google Cloud console, and configure it with `gsync config --service-account <KEY FILE> -f <INPUT FILES> -d <ID OF SHARED DRIVE>`. The Client ID and Secret and `gsync login` aren't needed then. Add the service account as a member of the shared drive, since the service account can't see your own Drive
+//!
+//! Besides `.gitignore` files, GSync honours `.gsyncignore` files, which use the same syntax but only affect GSync. Use these for files you want to keep in git but not back up, or vice versa. Patterns which apply to all inputs can be configured with `gsync config --exclude <PATTERNS> --include <PATTERNS>`, where included files are synced even if they are ignored. This includes files in ignored directories: with `build/` in a `.gitignore` file, `--include build/keep.txt` syncs `build/keep.txt` and nothing else from `build`. An include pattern without a `/`, e.g. `*.env`, matches at any depth, so every ignored directory is searched for matches
+//!
+//! By default GSync never removes anything from Google Drive. Run `gsync sync --delete` to move files and folders which no longer exist locally, or which are now ignored, to the trash. Add `--permanent` to delete them permanently instead
+//!
+//! To see what a sync would change without touching Google Drive, run `gsync sync --dry-run`. This can be combined with `--delete`
+//!
+//! Files of 5 MiB and larger are uploaded in chunks. If a sync is interrupted while uploading such a file, the next sync continues the upload where it left off
+//!
+//! Files are uploaded one at a time by default. To upload up to `N` files at the same time, run `gsync sync --jobs N`. Folders are still created in order, before anything is uploaded into them. A two-way sync syncs one file at a time, so `--jobs` can't be combined with `--two-way`
+//!
+//! A sync stops at the first file which fails to sync, e.g. because it can't be read. Run `gsync sync --continue-on-error` to sync everything else regardless. The files which failed are listed at the end of the run, together with the reason, and GSync exits with a non-zero exit code
+//!
+//! By default syncing only goes one way, from your computer to Google Drive. Run `gsync sync --two-way` to also download files which were added or changed in Google Drive since the previous sync. When a file was changed on both sides, the local version is uploaded and the version from Drive is kept next to it as `<NAME>.conflict-<TIMESTAMP>`. Combined with `--delete`, files removed on one side are removed on the other side as well. After the first two-way sync, GSync asks Google Drive which files changed since the previous sync, so only folders with remote changes have to be listed
+//!
+//! To get your files back, run `gsync restore --to <DIR> [REMOTE PATH]`. This downloads the given file or folder, or everything if no path is given, from the GSync folder in Google Drive into `<DIR>`
+//!
+//! Instead of Google Drive, GSync can also sync to a local directory, e.g. a NAS mount, with `gsync sync --local <DIR>`. Only the files have to be configured for this; no Google credentials or login are needed. Entries removed with `--delete` are moved to `<DIR>/.gsync-trash`, unless `--permanent` is given. Restore from such a directory with `gsync restore --local <DIR> --to <TARGET>`. Two-way syncing only gets the changed folders from Google Drive; with a local directory every folder is compared
+//!
 //! ## Licence
 //! GSync is dual licenced under the MIT and Apache-2.0 licence, at your discretion
 
@@ -37,44 +67,42 @@
 #![allow(clippy::multiple_crate_versions)]
 
 mod api;
+mod backend;
 mod env;
 mod config;
+mod error;
+mod jobs;
 mod login;
 mod macros;
+mod restore;
 mod sync;
 
 use clap::Arg;
 use crate::env::Env;
 use crate::config::Configuration;
-use crate::api::GoogleError;
-
-/// Type alias for Result
-pub type Result<T> = std::result::Result<T, (Error, u32, &'static str)>;
-
-/// Enum describing Errors which can often occur in Gsync
-#[derive(Debug)]
-pub enum Error {
-    /// Error returned by the Google API
-    GoogleError(GoogleError),
+use crate::api::drive::DriveClient;
+use crate::backend::{Backend, LocalBackend};
+use crate::jobs::SyncJob;
+use std::sync::Arc;
 
-    /// Error resulting from a database operation
-    DatabaseError(rusqlite::Error),
-
-    /// Error resulting from a reqwest operation
-    RequestError(reqwest::Error),
-
-    /// An error which does not fit in any other category
-    Other(String)
-}
+pub use crate::error::{Result, Error, ErrorKind};
 
 /// Version of the binary. Set in Cargo.toml
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
-fn main() {
+#[tokio::main]
+async fn main() {
     let matches = clap::App::new("gsync")
         .version(VERSION)
         .author("Tobias de Bruijn <t.debruijn@array21.dev>")
         .about("Sync folders and files to Google Drive while respecting gitignore files")
+        .arg(Arg::with_name("profile")
+            .long("profile")
+            .value_name("NAME")
+            .help("The profile to use. Every profile has its own configuration and login, e.g. to sync to multiple Google accounts. Defaults to 'default'")
+            .takes_value(true)
+            .global(true)
+            .required(false))
         .subcommand(clap::SubCommand::with_name("config")
             .about("Configure GSync. Not all options have to be supplied, if you don't want to overwrite them. If this is the first time you're running the config command, you must provide all options.")
             .arg(Arg::with_name("client-id")
@@ -104,18 +132,188 @@ fn main() {
                 .value_name("ID")
                 .help("The ID of the Team Drive to use, if you are not using a Team Drive leave this empty.")
                 .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("include")
+                .long("include")
+                .value_name("PATTERNS")
+                .help("Gitignore patterns of files to sync even if they are ignored by a .gitignore or .gsyncignore file, comma seperated String")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("exclude")
+                .long("exclude")
+                .value_name("PATTERNS")
+                .help("Gitignore patterns of files to never sync, comma seperated String")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("page-size")
+                .long("page-size")
+                .value_name("SIZE")
+                .help("The number of results to request per page when listing files in Google Drive, between 1 and 1000. Defaults to 1000")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("max-attempts")
+                .long("max-attempts")
+                .value_name("N")
+                .help("How often a request to Google is attempted when it is rate limited or fails temporarily, at least 1. Defaults to 5")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("root-folder")
+                .long("root-folder")
+                .value_name("NAME")
+                .help("The name of the folder in Google Drive everything is synced into. Defaults to 'GSync'")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("service-account")
+                .long("service-account")
+                .value_name("KEY_FILE")
+                .help("The JSON key of a Google service account to authenticate with, instead of logging in. The Client ID and Secret aren't needed then")
+                .takes_value(true)
                 .required(false)))
         .subcommand(clap::SubCommand::with_name("show")
             .about("Show the current GSync configuration"))
         .subcommand(clap::SubCommand::with_name("login")
-            .about("Login to Google"))
+            .about("Login to Google")
+            .arg(Arg::with_name("no-browser")
+                .long("no-browser")
+                .help("Login without a browser on this machine, e.g. over SSH. Open the printed URL on any machine and paste the code or the URL you end up at")
+                .required(false))
+            .arg(Arg::with_name("device")
+                .long("device")
+                .help("Login by entering a code on another device. Requires OAuth2 credentials of type 'TVs and Limited Input devices'. GSync then only has access to the files it created itself")
+                .conflicts_with("no-browser")
+                .required(false)))
         .subcommand(clap::SubCommand::with_name("sync")
-            .about("Start syncing the configured folders to Google Drive"))
+            .about("Start syncing the configured folders to Google Drive, followed by all sync jobs. If a job is given, only that job is run")
+            .arg(Arg::with_name("job")
+                .value_name("JOB")
+                .help("The name of the sync job to run")
+                .conflicts_with("local")
+                .required(false))
+            .arg(Arg::with_name("delete")
+                .long("delete")
+                .help("Remove files and folders from Google Drive which no longer exist locally or are now ignored. They are moved to the trash, unless --permanent is given")
+                .required(false))
+            .arg(Arg::with_name("permanent")
+                .long("permanent")
+                .help("Permanently delete removed files and folders, instead of moving them to the trash")
+                .requires("delete")
+                .required(false))
+            .arg(Arg::with_name("dry-run")
+                .long("dry-run")
+                .help("Compare the local files against Google Drive and print what would be created, updated and deleted, without changing anything")
+                .required(false))
+            .arg(Arg::with_name("two-way")
+                .long("two-way")
+                .help("Also download changes made in Google Drive. Files changed on both sides are kept twice, the copy from Drive is saved as <NAME>.conflict-<TIMESTAMP>")
+                .required(false))
+            .arg(Arg::with_name("jobs")
+                .short("j")
+                .long("jobs")
+                .value_name("N")
+                .help("The maximum number of files to upload at the same time. Defaults to 1. A two-way sync always syncs one file at a time")
+                .takes_value(true)
+                .conflicts_with("two-way")
+                .required(false))
+            .arg(Arg::with_name("continue-on-error")
+                .long("continue-on-error")
+                .help("Continue with the other files when a file fails to sync. The failed files are listed at the end, and GSync exits with a non-zero exit code")
+                .required(false))
+            .arg(Arg::with_name("local")
+                .long("local")
+                .value_name("DIR")
+                .help("Sync to a local directory, e.g. a NAS mount, instead of Google Drive. No Google credentials or login are needed. Sync jobs aren't run then")
+                .takes_value(true)
+                .required(false)))
+        .subcommand(clap::SubCommand::with_name("job")
+            .about("Manage sync jobs: local directories which are synced to their own destination in Google Drive")
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(clap::SubCommand::with_name("add")
+                .about("Add a sync job, or replace the sync job with the same name. The contents of the source directory are synced into the destination folder")
+                .arg(Arg::with_name("name")
+                    .value_name("NAME")
+                    .help("The name of the sync job")
+                    .required(true))
+                .arg(Arg::with_name("source")
+                    .long("source")
+                    .value_name("DIR")
+                    .help("The local directory to sync")
+                    .takes_value(true)
+                    .required(true))
+                .arg(Arg::with_name("to")
+                    .long("to")
+                    .value_name("FOLDER_PATH")
+                    .help("The path of the destination folder, relative to the root of My Drive or of the shared drive, e.g. 'Backups/Notes'. Missing folders are created")
+                    .takes_value(true)
+                    .required_unless("to-id"))
+                .arg(Arg::with_name("to-id")
+                    .long("to-id")
+                    .value_name("ID")
+                    .help("The ID of the destination folder, instead of its path")
+                    .takes_value(true)
+                    .conflicts_with("to"))
+                .arg(Arg::with_name("drive_id")
+                    .short("d")
+                    .long("drive")
+                    .value_name("ID")
+                    .help("The ID of the Team Drive to sync to. If omitted, the job syncs to My Drive")
+                    .takes_value(true)
+                    .required(false))
+                .arg(Arg::with_name("include")
+                    .long("include")
+                    .value_name("PATTERNS")
+                    .help("Gitignore patterns of files to sync even if they are ignored by a .gitignore or .gsyncignore file, comma seperated String")
+                    .takes_value(true)
+                    .required(false))
+                .arg(Arg::with_name("exclude")
+                    .long("exclude")
+                    .value_name("PATTERNS")
+                    .help("Gitignore patterns of files to never sync, comma seperated String")
+                    .takes_value(true)
+                    .required(false)))
+            .subcommand(clap::SubCommand::with_name("list")
+                .about("List the sync jobs"))
+            .subcommand(clap::SubCommand::with_name("remove")
+                .about("Remove a sync job. Nothing is removed from Google Drive")
+                .arg(Arg::with_name("name")
+                    .value_name("NAME")
+                    .help("The name of the sync job")
+                    .required(true))))
+        .subcommand(clap::SubCommand::with_name("restore")
+            .about("Download files from Google Drive, recreating the folder structure")
+            .arg(Arg::with_name("to")
+                .short("t")
+                .long("to")
+                .value_name("DIR")
+                .help("The directory to restore to. Defaults to the current directory")
+                .takes_value(true)
+                .required(false))
+            .arg(Arg::with_name("remote-path")
+                .value_name("REMOTE_PATH")
+                .help("The file or folder to restore, relative to the GSync folder in Google Drive. If omitted, everything is restored")
+                .required(false))
+            .arg(Arg::with_name("local")
+                .long("local")
+                .value_name("DIR")
+                .help("Restore from a local directory files were synced to with `gsync sync --local`, instead of Google Drive")
+                .takes_value(true)
+                .required(false)))
         .subcommand(clap::SubCommand::with_name("drives")
             .about("Get a list of all shared drives and their IDs."))
+        .subcommand(clap::SubCommand::with_name("logout")
+            .about("Logout from Google. The login is revoked with Google, and GSync forgets what it knows about the files in your Drive"))
+        .subcommand(clap::SubCommand::with_name("whoami")
+            .about("Show the Google account GSync is logged in with, and when its access token expires"))
         .get_matches();
 
-    let empty_env = Env::empty();
+    let mut empty_env = Env::empty();
+    if let Some(profile) = matches.value_of("profile") {
+        if !is_valid_name(profile) {
+            eprintln!("Error: A profile name may only contain letters, digits, '-' and '_'");
+            std::process::exit(1);
+        }
+
+        empty_env.profile = profile.to_string();
+    }
 
     // Scoping this seperately because we want to drop conn when we're done, since we can only ever have 1 conn.
     {
@@ -123,21 +321,75 @@ fn main() {
         let conn = empty_env.get_conn().expect("Failed to create database connection. ");
         conn.execute("CREATE TABLE IF NOT EXISTS user (id TEXT PRIMARY KEY, refresh_token TEXT, access_token TEXT, expiry INTEGER)", rusqlite::named_params! {}).expect("Failed to create table 'users'");
         conn.execute("CREATE TABLE IF NOT EXISTS config (client_id TEXT, client_secret TEXT, input_files TEXT, drive_id TEXT)", rusqlite::named_params! {}).expect("Failed to create table 'config'");
+        add_column_if_missing(&conn, "config", "include_patterns", "TEXT").expect("Failed to add column 'include_patterns' to table 'config'");
+        add_column_if_missing(&conn, "config", "exclude_patterns", "TEXT").expect("Failed to add column 'exclude_patterns' to table 'config'");
+        add_column_if_missing(&conn, "config", "page_size", "INTEGER").expect("Failed to add column 'page_size' to table 'config'");
+        add_column_if_missing(&conn, "config", "max_attempts", "INTEGER").expect("Failed to add column 'max_attempts' to table 'config'");
+        add_column_if_missing(&conn, "config", "service_account_key", "TEXT").expect("Failed to add column 'service_account_key' to table 'config'");
+        add_column_if_missing(&conn, "config", "root_folder", "TEXT").expect("Failed to add column 'root_folder' to table 'config'");
+        add_column_if_missing(&conn, "config", "profile", "TEXT NOT NULL DEFAULT 'default'").expect("Failed to add column 'profile' to table 'config'");
+        add_column_if_missing(&conn, "user", "profile", "TEXT NOT NULL DEFAULT 'default'").expect("Failed to add column 'profile' to table 'user'");
+        conn.execute("CREATE TABLE IF NOT EXISTS sync_state (profile TEXT NOT NULL, root_id TEXT NOT NULL, path TEXT NOT NULL, drive_id TEXT NOT NULL, parent_id TEXT NOT NULL, is_dir INTEGER NOT NULL, size INTEGER NOT NULL, mtime INTEGER NOT NULL, checksum TEXT, PRIMARY KEY (profile, root_id, path))", rusqlite::named_params! {}).expect("Failed to create table 'sync_state'");
+        conn.execute("CREATE TABLE IF NOT EXISTS change_tokens (profile TEXT NOT NULL, root_id TEXT NOT NULL, page_token TEXT NOT NULL, PRIMARY KEY (profile, root_id))", rusqlite::named_params! {}).expect("Failed to create table 'change_tokens'");
+        conn.execute("CREATE TABLE IF NOT EXISTS sync_jobs (profile TEXT NOT NULL, name TEXT NOT NULL, source TEXT NOT NULL, destination TEXT, destination_id TEXT, drive_id TEXT, include_patterns TEXT, exclude_patterns TEXT, PRIMARY KEY (profile, name))", rusqlite::named_params! {}).expect("Failed to create table 'sync_jobs'");
+        // Upload sessions used to be stored by path only. They only allow continuing interrupted uploads, so those are dropped
+        if !has_column(&conn, "upload_sessions", "root_id").expect("Failed to read the columns of table 'upload_sessions'") {
+            conn.execute("DROP TABLE IF EXISTS upload_sessions", rusqlite::named_params! {}).expect("Failed to drop table 'upload_sessions'");
+        }
+
+        conn.execute("CREATE TABLE IF NOT EXISTS upload_sessions (profile TEXT NOT NULL, root_id TEXT NOT NULL, path TEXT NOT NULL, session_uri TEXT NOT NULL, file_id TEXT NOT NULL, target TEXT NOT NULL, size INTEGER NOT NULL, mtime INTEGER NOT NULL, PRIMARY KEY (profile, root_id, path))", rusqlite::named_params! {}).expect("Failed to create table 'upload_sessions'");
     }
 
     // 'config' subcommand
     if let Some(matches) = matches.subcommand_matches("config") {
+        let page_size = match matches.value_of("page-size").map(str::parse::<u32>) {
+            Some(Ok(page_size)) if (1..=1000).contains(&page_size) => Some(page_size),
+            Some(_) => {
+                eprintln!("Error: The page size must be a number between 1 and 1000");
+                std::process::exit(1);
+            },
+            None => None
+        };
+
+        let max_attempts = match matches.value_of("max-attempts").map(str::parse::<u32>) {
+            Some(Ok(max_attempts)) if max_attempts >= 1 => Some(max_attempts),
+            Some(_) => {
+                eprintln!("Error: The maximum number of attempts must be a number of at least 1");
+                std::process::exit(1);
+            },
+            None => None
+        };
+
+        let service_account_key = match matches.value_of("service-account").map(std::fs::canonicalize) {
+            Some(Ok(path)) => Some(path.to_string_lossy().to_string()),
+            Some(Err(e)) => {
+                eprintln!("Error: The service account key can't be found: {}", e);
+                std::process::exit(1);
+            },
+            None => None
+        };
+
         let new_config = Configuration {
             client_id:      option_str_string(matches.value_of("client-id")),
             client_secret:  option_str_string(matches.value_of("client-secret")),
             input_files:    option_str_string(matches.value_of("files")),
-            drive_id:       option_str_string(matches.value_of("drive_id"))
+            drive_id:       option_str_string(matches.value_of("drive_id")),
+            include_patterns:   option_str_string(matches.value_of("include")),
+            exclude_patterns:   option_str_string(matches.value_of("exclude")),
+            page_size,
+            max_attempts,
+            service_account_key,
+            root_folder:        option_str_string(matches.value_of("root-folder"))
         };
 
         let current_config = handle_err!(Configuration::get_config(&empty_env));
         let config = Configuration::merge(new_config, current_config);
         match config.is_complete() {
             (true, _) => {},
+            // Syncing to a local directory only needs the files
+            (false, str) if config.input_files.is_some() => {
+                eprintln!("Warning: Configuration is incomplete; {}. Only `gsync sync --local` and `gsync restore --local` can be used", str);
+            },
             (false, str) => {
                 eprintln!("Error: Configuration is incomplete; {}", str);
                 std::process::exit(1);
@@ -159,16 +411,22 @@ fn main() {
             std::process::exit(0);
         }
 
-        println!("Current GSync configuration:");
+        println!("Current GSync configuration of profile '{}':", empty_env.profile);
         println!("Client ID: {}", option_unwrap_text(config.client_id));
         println!("Client Secret: {}", option_unwrap_text(config.client_secret));
         println!("Input Files: {}", option_unwrap_text(config.input_files));
         println!("Drive ID: {}", option_unwrap_text(config.drive_id));
+        println!("Include Patterns: {}", option_unwrap_text(config.include_patterns));
+        println!("Exclude Patterns: {}", option_unwrap_text(config.exclude_patterns));
+        println!("Page Size: {}", option_unwrap_text(config.page_size.map(|page_size| page_size.to_string())));
+        println!("Max Attempts: {}", option_unwrap_text(config.max_attempts.map(|max_attempts| max_attempts.to_string())));
+        println!("Service Account Key: {}", option_unwrap_text(config.service_account_key));
+        println!("Root Folder: {}", option_unwrap_text(config.root_folder));
         std::process::exit(0);
     }
 
     // 'login' subcommand
-    if matches.subcommand_matches("login").is_some() {
+    if let Some(matches) = matches.subcommand_matches("login") {
         let config = handle_err!(Configuration::get_config(&empty_env));
 
         if config.is_empty() {
@@ -184,9 +442,19 @@ fn main() {
             }
         }
 
-        // Safe to call unwrap because we've verified that the config is complete
-        let env = Env::new(config.client_id.as_ref().unwrap(), config.client_secret.as_ref().unwrap(), config.drive_id.as_ref(), String::new());
-        let login_data = handle_err!(crate::login::perform_oauth2_login(&env));
+        if config.service_account_key.is_some() {
+            println!("Info: GSync authenticates with the configured service account, logging in isn't needed.");
+            std::process::exit(0);
+        }
+
+        let env = env_from_config(&config, &empty_env.profile);
+        let login_data = if matches.is_present("device") {
+            handle_err!(crate::login::perform_device_login(&env, &reqwest::Client::new()).await)
+        } else if matches.is_present("no-browser") {
+            handle_err!(crate::login::perform_headless_login(&env, &reqwest::Client::new()).await)
+        } else {
+            handle_err!(crate::login::perform_oauth2_login(&env, &reqwest::Client::new()).await)
+        };
 
         println!("Info: Inserting tokens into database.");
         handle_err!(crate::login::db::save_to_database(&login_data, &env));
@@ -195,7 +463,7 @@ fn main() {
     }
 
     // 'sync' subcommand
-    if matches.subcommand_matches("sync").is_some() {
+    if let Some(matches) = matches.subcommand_matches("sync") {
         let config = handle_err!(Configuration::get_config(&empty_env));
 
         if config.is_empty() {
@@ -203,39 +471,232 @@ fn main() {
             std::process::exit(0);
         }
 
+        let local = matches.value_of("local");
         match config.is_complete() {
             (true, _) => {},
+            // Syncing to a local directory doesn't need the Google credentials
+            (false, _) if local.is_some() && config.input_files.is_some() => {},
             (false, str) => {
                 eprintln!("Error: Configuration is incomplete; {}", str);
                 std::process::exit(1);
             }
         }
 
-        if !handle_err!(is_logged_in(&empty_env)) {
+        if local.is_none() && config.service_account_key.is_none() && !handle_err!(is_logged_in(&empty_env)) {
             eprintln!("Error: GSync isn't logged in with Google. Have you run `gsync login` yet?");
             std::process::exit(1);
         }
 
-        // Safe to call unwrap because we verified the config is complete above
-        let mut env = Env::new(config.client_id.as_ref().unwrap(), config.client_secret.as_ref().unwrap(), config.drive_id.as_ref(), String::new());
+        let job_name = matches.value_of("job");
+        let sync_jobs = match job_name {
+            Some(name) => match handle_err!(SyncJob::get(&empty_env, name)) {
+                Some(job) => vec![job],
+                None => {
+                    eprintln!("Error: There is no sync job named '{}'. Run `gsync job list` to see the sync jobs", name);
+                    std::process::exit(1);
+                }
+            },
+            // Sync jobs always sync to Google Drive
+            None if local.is_some() => Vec::new(),
+            None => handle_err!(SyncJob::list(&empty_env))
+        };
 
-        println!("Info: Querying Drive for root folder");
-        let list = handle_err!(crate::api::drive::list_files(&env, Some("name = 'GSync' and mimeType = 'application/vnd.google-apps.folder' and trashed = false"), config.drive_id.as_deref()));
+        let sync_files = job_name.is_none() && config.input_files.is_some();
+        if !sync_files && sync_jobs.is_empty() {
+            eprintln!("Error: There is nothing to sync. Configure files with `gsync config --files <FILES>`, or add a sync job with `gsync job add`");
+            std::process::exit(1);
+        }
+
+        let jobs = match matches.value_of("jobs").map(str::parse::<usize>) {
+            Some(Ok(jobs)) if jobs >= 1 => jobs,
+            Some(_) => {
+                eprintln!("Error: The number of jobs must be a number of at least 1");
+                std::process::exit(1);
+            },
+            None => 1
+        };
+
+        let options = crate::sync::SyncOptions {
+            delete:     matches.is_present("delete"),
+            permanent:  matches.is_present("permanent"),
+            dry_run:    matches.is_present("dry-run"),
+            two_way:    matches.is_present("two-way"),
+            jobs,
+            continue_on_error:  matches.is_present("continue-on-error"),
+            into_root:          false
+        };
 
-        let root_folder_id = if list.is_empty() {
-            println!("Info: Root folder doesn't exist. Creating one now.");
-            match &env.drive_id {
-                Some(drive_id) => handle_err!(crate::api::drive::create_folder(&env, "GSync", drive_id)),
-                None => handle_err!(crate::api::drive::create_folder(&env, "GSync", "root"))
+        let mut failed = 0;
+        if sync_files {
+            let mut env = env_from_config(&config, &empty_env.profile);
+            let client = DriveClient::new(&env);
+            let backend: Arc<dyn Backend> = match local {
+                Some(local) => Arc::new(handle_err!(LocalBackend::new(std::path::Path::new(local)))),
+                None => Arc::new(client.clone())
+            };
+
+            println!("Info: Looking for the {}", backend.describe_root());
+            let root_folder_id = match handle_err!(backend.find_root().await) {
+                Some(root_folder_id) => {
+                    println!("Info: The {} exists.", backend.describe_root());
+                    root_folder_id
+                },
+                None if options.dry_run => {
+                    // An empty root folder ID tells sync that everything still has to be created
+                    println!("Info: The {} doesn't exist. It would be created.", backend.describe_root());
+                    String::new()
+                },
+                None => {
+                    println!("Info: The {} doesn't exist. Creating it now.", backend.describe_root());
+                    handle_err!(backend.create_root().await)
+                }
+            };
+
+            env.root_folder = root_folder_id;
+            let backend: Arc<dyn Backend> = match local {
+                Some(_) => backend,
+                None => Arc::new(client.with_env(&env))
+            };
+
+            failed += handle_err!(crate::sync::sync(&config, &env, &backend, &options).await);
+        }
+
+        // The destination folder of a job stands for its source directory, so the contents of the source are synced into it
+        let job_options = crate::sync::SyncOptions { into_root: true, ..options };
+        for job in sync_jobs {
+            println!("Info: Running sync job '{}', syncing '{}' to {}", job.name, job.source, job.describe_destination());
+            let mut env = env_from_config(&config, &empty_env.profile);
+            env.drive_id = job.drive_id.clone();
+            let client = DriveClient::new(&env);
+
+            env.root_folder = match handle_err!(job.find_destination(&client, !job_options.dry_run).await) {
+                Some(destination_id) => destination_id,
+                None => {
+                    println!("Info: Destination folder doesn't exist. It would be created.");
+                    String::new()
+                }
+            };
+
+            let backend: Arc<dyn Backend> = Arc::new(client.with_env(&env));
+            failed += handle_err!(crate::sync::sync(&job.config(), &env, &backend, &job_options).await);
+        }
+
+        std::process::exit(if failed > 0 { 1 } else { 0 });
+    }
+
+    // 'job' subcommand
+    if let Some(matches) = matches.subcommand_matches("job") {
+        if let Some(matches) = matches.subcommand_matches("add") {
+            let name = matches.value_of("name").unwrap();
+            if !is_valid_name(name) {
+                eprintln!("Error: A sync job name may only contain letters, digits, '-' and '_'");
+                std::process::exit(1);
             }
-        } else {
-            println!("Info: Root folder exists.");
-            list.get(0).unwrap().id.clone()
+
+            // Unwrap is safe because the source is required
+            let source = match std::fs::canonicalize(matches.value_of("source").unwrap()) {
+                Ok(source) if source.is_dir() => source.to_string_lossy().to_string(),
+                Ok(_) => {
+                    eprintln!("Error: The source of a sync job must be a directory");
+                    std::process::exit(1);
+                },
+                Err(e) => {
+                    eprintln!("Error: The source directory can't be found: {}", e);
+                    std::process::exit(1);
+                }
+            };
+
+            let job = SyncJob {
+                name:               name.to_string(),
+                source,
+                destination:        option_str_string(matches.value_of("to")),
+                destination_id:     option_str_string(matches.value_of("to-id")),
+                drive_id:           option_str_string(matches.value_of("drive_id")),
+                include_patterns:   option_str_string(matches.value_of("include")),
+                exclude_patterns:   option_str_string(matches.value_of("exclude"))
+            };
+
+            match job.is_valid() {
+                (true, _) => {},
+                (false, str) => {
+                    eprintln!("Error: Invalid sync job; {}", str);
+                    std::process::exit(1);
+                }
+            }
+
+            handle_err!(job.write(&empty_env));
+            println!("Sync job '{}' added!", job.name);
+        }
+
+        if matches.subcommand_matches("list").is_some() {
+            let sync_jobs = handle_err!(SyncJob::list(&empty_env));
+            if sync_jobs.is_empty() {
+                println!("Profile '{}' has no sync jobs. Run `gsync job add -h` for more information on how to add one", empty_env.profile);
+            }
+
+            for job in sync_jobs {
+                println!("Sync job '{}': '{}' to {}", job.name, job.source, job.describe_destination());
+                println!("  Include Patterns: {}", option_unwrap_text(job.include_patterns));
+                println!("  Exclude Patterns: {}", option_unwrap_text(job.exclude_patterns));
+            }
+        }
+
+        if let Some(matches) = matches.subcommand_matches("remove") {
+            let name = matches.value_of("name").unwrap();
+            if !handle_err!(SyncJob::delete(&empty_env, name)) {
+                eprintln!("Error: There is no sync job named '{}'", name);
+                std::process::exit(1);
+            }
+
+            println!("Sync job '{}' removed!", name);
+        }
+
+        std::process::exit(0);
+    }
+
+    // 'restore' subcommand
+    if let Some(matches) = matches.subcommand_matches("restore") {
+        let config = handle_err!(Configuration::get_config(&empty_env));
+        let local = matches.value_of("local");
+
+        if config.is_empty() && local.is_none() {
+            println!("GSync is unconfigured. Run 'gsync config -h` for more information on how to configure GSync'");
+            std::process::exit(0);
+        }
+
+        match config.is_complete() {
+            (true, _) => {},
+            // Restoring from a local directory doesn't need the Google credentials, nor anything else
+            (false, _) if local.is_some() => {},
+            (false, str) => {
+                eprintln!("Error: Configuration is incomplete; {}", str);
+                std::process::exit(1);
+            }
+        }
+
+        if local.is_none() && config.service_account_key.is_none() && !handle_err!(is_logged_in(&empty_env)) {
+            eprintln!("Error: GSync isn't logged in with Google. Have you run `gsync login` yet?");
+            std::process::exit(1);
+        }
+
+        let mut env = env_from_config(&config, &empty_env.profile);
+        let backend: Arc<dyn Backend> = match local {
+            Some(local) => Arc::new(handle_err!(LocalBackend::new(std::path::Path::new(local)))),
+            None => Arc::new(DriveClient::new(&env))
         };
 
-        env.root_folder = root_folder_id;
+        println!("Info: Looking for the {}", backend.describe_root());
+        env.root_folder = match handle_err!(backend.find_root().await) {
+            Some(root_folder_id) => root_folder_id,
+            None => {
+                eprintln!("Error: The {} doesn't exist, there is nothing to restore from. Have you run `gsync sync` yet?", backend.describe_root());
+                std::process::exit(1);
+            }
+        };
 
-        handle_err!(crate::sync::sync(&config, &env));
+        let target = std::path::PathBuf::from(matches.value_of("to").unwrap_or("."));
+        handle_err!(crate::restore::restore(&env, backend.as_ref(), matches.value_of("remote-path"), &target).await);
+        println!("Info: Restore complete!");
         std::process::exit(0);
     }
 
@@ -255,13 +716,14 @@ fn main() {
             }
         }
 
-        if !handle_err!(is_logged_in(&empty_env)) {
+        if config.service_account_key.is_none() && !handle_err!(is_logged_in(&empty_env)) {
             eprintln!("Error: GSync isn't logged in with Google. Have you run `gsync login` yet?");
             std::process::exit(1);
         }
 
-        let env = Env::new(config.client_id.as_ref().unwrap(), config.client_secret.as_ref().unwrap(), config.drive_id.as_ref(), String::new());
-        let shared_drives = handle_err!(crate::api::drive::get_shared_drives(&env));
+        let env = env_from_config(&config, &empty_env.profile);
+        let client = DriveClient::new(&env);
+        let shared_drives = handle_err!(client.get_shared_drives().await);
         for drive in shared_drives {
             println!("Shared drive '{}' with identifier '{}'", &drive.name, &drive.id);
         }
@@ -269,6 +731,71 @@ fn main() {
         std::process::exit(0);
     }
 
+    // 'logout' subcommand
+    if matches.subcommand_matches("logout").is_some() {
+        let config = handle_err!(Configuration::get_config(&empty_env));
+
+        let tokens = handle_err!(crate::api::oauth::stored_tokens(&empty_env));
+        let (_, refresh_token, _) = match tokens {
+            Some(tokens) => tokens,
+            None => {
+                println!("Info: GSync isn't logged in.");
+                std::process::exit(0);
+            }
+        };
+
+        let env = env_from_config(&config, &empty_env.profile);
+
+        // Forget the login even if revoking fails, e.g. because it was already revoked in the Google account settings
+        println!("Info: Revoking the login with Google.");
+        if let Err(e) = crate::api::oauth::revoke_token(&env, &reqwest::Client::new(), &refresh_token).await {
+            eprintln!("Warning: Revoking the login failed: {}", e.report());
+        }
+
+        handle_err!(crate::login::db::delete_from_database(&env));
+        println!("Info: Logout successful!");
+        if config.service_account_key.is_some() {
+            println!("Info: GSync still authenticates with the configured service account.");
+        }
+
+        std::process::exit(0);
+    }
+
+    // 'whoami' subcommand
+    if matches.subcommand_matches("whoami").is_some() {
+        let config = handle_err!(Configuration::get_config(&empty_env));
+
+        if config.is_empty() {
+            println!("GSync is unconfigured. Run 'gsync config -h` for more information on how to configure GSync'");
+            std::process::exit(0);
+        }
+
+        let tokens = handle_err!(crate::api::oauth::stored_tokens(&empty_env));
+        if config.service_account_key.is_none() && tokens.is_none() {
+            eprintln!("Error: GSync isn't logged in with Google. Have you run `gsync login` yet?");
+            std::process::exit(1);
+        }
+
+        let env = env_from_config(&config, &empty_env.profile);
+        let client = DriveClient::new(&env);
+        let about = handle_err!(client.about().await);
+
+        match about.user.email_address {
+            Some(email_address) => println!("Logged in as {} <{}>", about.user.display_name, email_address),
+            None => println!("Logged in as {}", about.user.display_name)
+        }
+
+        if let Some(key) = &config.service_account_key {
+            println!("Authenticated with the service account key '{}'", key);
+        } else if let Some((_, _, expiry)) = handle_err!(crate::api::oauth::stored_tokens(&empty_env)) {
+            // Read again, since getting the account may have refreshed the access token
+            let expiry = chrono::TimeZone::timestamp(&chrono::Local, expiry, 0);
+            println!("The access token expires at {}. It is refreshed automatically", expiry.format("%Y-%m-%d %H:%M:%S"));
+        }
+
+        std::process::exit(0);
+    }
+
     println!("No command specified. Run 'gsync -h' for available commands.");
 }
 
@@ -285,14 +812,57 @@ fn option_unwrap_text(i: Option<String>) -> String {
     }
 }
 
-/// Check if a user is logged in
+/// Check if `name` can be used as the name of a profile or sync job: it may only contain letters, digits, '-' and '_'
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Add a column to a table if it doesn't exist yet, for databases created by an older version of GSync
+///
+/// # Errors
+/// - When a database operation fails
+fn add_column_if_missing(conn: &rusqlite::Connection, table: &str, column: &str, definition: &str) -> rusqlite::Result<()> {
+    if !has_column(conn, table, column)? {
+        conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), rusqlite::named_params! {})?;
+    }
+
+    Ok(())
+}
+
+/// Check if a table has a column. A table which doesn't exist has no columns
+///
+/// # Errors
+/// - When a database operation fails
+fn has_column(conn: &rusqlite::Connection, table: &str, column: &str) -> rusqlite::Result<bool> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let mut columns = stmt.query_map(rusqlite::named_params! {}, |row| row.get::<_, String>("name"))?;
+    Ok(columns.any(|name| matches!(name, Ok(name) if name == column)))
+}
+
+/// Create the Env of the profile `profile` from its configuration
+fn env_from_config(config: &Configuration, profile: &str) -> Env {
+    let mut env = Env::new(config.client_id.as_deref().unwrap_or_default(), config.client_secret.as_deref().unwrap_or_default(), config.drive_id.as_ref(), String::new());
+    env.page_size = config.page_size;
+    env.max_attempts = config.max_attempts;
+    env.service_account_key = config.service_account_key.clone();
+    env.profile = profile.to_string();
+    if let Some(root_folder) = &config.root_folder {
+        env.root_folder_name = root_folder.clone();
+    }
+
+    env
+}
+
+/// Check if a user is logged in with the profile of `env`
 ///
 /// # Errors
 /// - When a database operation fails
 fn is_logged_in(env: &Env) -> Result<bool> {
     let conn = unwrap_db_err!(env.get_conn());
-    let mut stmt = unwrap_db_err!(conn.prepare("SELECT * FROM user"));
-    let mut result = unwrap_db_err!(stmt.query(rusqlite::named_params! {}));
+    let mut stmt = unwrap_db_err!(conn.prepare("SELECT * FROM user WHERE profile = :profile"));
+    let mut result = unwrap_db_err!(stmt.query(rusqlite::named_params! {
+        ":profile": &env.profile
+    }));
 
     let mut is_logged_in = false;
     while let Ok(Some(_)) = result.next() {
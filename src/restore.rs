@@ -0,0 +1,101 @@
+//! Module related to restoring files from Google Drive
+
+use crate::env::Env;
+use crate::{Result, ErrorKind, unwrap_io_err, new_err};
+use crate::error::ResultExt;
+use crate::api::drive::{self, FOLDER_MIME_TYPE};
+use crate::backend::Backend;
+use crate::sync::{BoxFuture, md5_checksum};
+use std::path::Path;
+use std::fs;
+
+/// Restore files from the root folder in Google Drive to the local directory `target`, recreating the folder structure.
+///
+/// If `remote_path` is given, only the file or folder at that path, relative to the root folder, is restored into `target`.
+/// Otherwise the entire contents of the root folder are restored into `target`.
+///
+/// # Errors
+/// - When `remote_path` doesn't exist in Google Drive
+/// - When a request to Google fails
+/// - When writing a local file or directory fails
+pub async fn restore(env: &Env, backend: &dyn Backend, remote_path: Option<&str>, target: &Path) -> Result<()> {
+    unwrap_io_err!(fs::create_dir_all(target));
+
+    let remote_path = match remote_path {
+        Some(remote_path) => remote_path,
+        None => {
+            println!("Info: Restoring everything to '{}'", target.to_str().unwrap());
+            return restore_folder(backend, &env.root_folder, target).await;
+        }
+    };
+
+    let mut parent_id = env.root_folder.clone();
+    let mut components = remote_path.split('/').filter(|c| !c.is_empty()).peekable();
+    while let Some(component) = components.next() {
+        println!("Info: Querying {} for '{}'", backend.describe(), component);
+        let query_result = backend.find(&parent_id, component, false).await?;
+        let file = match query_result.into_iter().next() {
+            Some(file) => file,
+            None => return Err(new_err!(ErrorKind::Other(format!("'{}' does not exist in {}", remote_path, backend.describe()))))
+        };
+
+        if components.peek().is_some() {
+            parent_id = file.id;
+            continue;
+        }
+
+        println!("Info: Restoring '{}' to '{}'", remote_path, target.to_str().unwrap());
+        return restore_entry(backend, &file, target).await;
+    }
+
+    // The remote path consisted of only slashes, which refers to the root folder
+    restore_folder(backend, &env.root_folder, target).await
+}
+
+/// Restore the contents of a folder in Google Drive into the local directory `target`. This is a recursive function
+///
+/// # Errors
+/// - When a request to Google fails
+/// - When writing a local file or directory fails
+async fn restore_folder(backend: &dyn Backend, folder_id: &str, target: &Path) -> Result<()> {
+    let children = backend.list_folder(folder_id).await?;
+    for child in children {
+        restore_entry(backend, &child, target).await?;
+    }
+
+    Ok(())
+}
+
+/// Restore a single file or folder from Google Drive into the local directory `target`
+///
+/// # Errors
+/// - When a request to Google fails
+/// - When writing a local file or directory fails
+fn restore_entry<'a>(backend: &'a dyn Backend, file: &'a drive::File, target: &'a Path) -> BoxFuture<'a, Result<()>> {
+    Box::pin(async move {
+        let path = target.join(&file.name);
+
+        if file.mime_type == FOLDER_MIME_TYPE {
+            unwrap_io_err!(fs::create_dir_all(&path));
+            return restore_folder(backend, &file.id, &path).await;
+        }
+
+        // Google Docs, Sheets etc. have no binary content which could be downloaded
+        if file.mime_type.starts_with("application/vnd.google-apps.") {
+            eprintln!("Warning: Skipping '{}', files of type '{}' can't be downloaded", path.to_str().unwrap(), &file.mime_type);
+            return Ok(());
+        }
+
+        if path.is_file() {
+            if let Some(checksum) = backend.checksum(file).await? {
+                if checksum == md5_checksum(&path)? {
+                    println!("Info: File '{}' is up-to-date.", path.to_str().unwrap());
+                    return Ok(());
+                }
+            }
+        }
+
+        println!("Info: Downloading file '{}'", path.to_str().unwrap());
+        backend.download_file(&file.id, &path).await.with_path(&path).with_file_id(&file.id)
+    })
+}
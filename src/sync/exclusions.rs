@@ -0,0 +1,380 @@
+//! Module for matching paths against gitignore rules, `.gsyncignore` rules and the configured include and exclude patterns
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use std::path::{Path, PathBuf};
+use std::fs;
+use crate::error::ResultExt;
+use crate::{Result, unwrap_io_err};
+
+/// Struct describing the gitignore rules defined in a single directory
+struct Frame {
+    /// The directory the rules were defined in
+    dir:            PathBuf,
+
+    /// Rules from the `.gitignore` file in this directory
+    gitignore:      Option<Gitignore>,
+
+    /// Rules from the `.gsyncignore` file in this directory
+    gsyncignore:    Option<Gitignore>,
+
+    /// Rules from `.git/info/exclude`, if this directory is the root of a git repository
+    info_exclude:   Option<Gitignore>,
+
+    /// Whether this directory is the root of a git repository
+    is_repository:  bool
+}
+
+/// Stack of gitignore rule sets. The rules of a directory are pushed when entering it, and popped when leaving it again,
+/// so rules never apply outside of the directory they were defined in
+pub struct ExclusionStack {
+    /// The configured include patterns. A path matching any of these is never excluded
+    include:    Gitignore,
+
+    /// The configured include patterns as they were given, to find the excluded directories they could match entries in
+    include_patterns:   Vec<String>,
+
+    /// The configured exclude patterns
+    exclude:    Gitignore,
+
+    /// Rules from the file configured as git's `core.excludesFile`
+    global:     Gitignore,
+
+    /// The rules per directory, the innermost directory last
+    frames: Vec<Frame>
+}
+
+impl ExclusionStack {
+    /// Create a stack for traversing `input`. If `input` is inside of a git repository,
+    /// the rules of the directories between the root of that repository and `input` are pushed onto the stack.
+    ///
+    /// `include` and `exclude` are comma seperated lists of gitignore patterns, which are matched relative to `input`
+    ///
+    /// # Errors
+    /// - When reading any of the gitignore files fails
+    pub fn new(input: &Path, include: Option<&str>, exclude: Option<&str>) -> Result<Self> {
+        let (global, error) = Gitignore::global();
+        if let Some(e) = error {
+            eprintln!("Warning: Failed to parse your global git excludes file: {}", e);
+        }
+
+        let include_patterns = include.unwrap_or_default().split(',').map(str::to_string).collect::<Vec<_>>();
+        let include = build_rules(input, &include_patterns.join("\n"), Path::new("include patterns"));
+        let exclude = build_rules(input, &exclude.unwrap_or_default().replace(',', "\n"), Path::new("exclude patterns"));
+        let mut stack = Self { include, include_patterns, exclude, global, frames: Vec::new() };
+
+        let ancestors = input.ancestors().skip(1).collect::<Vec<_>>();
+        if let Some(repository) = ancestors.iter().position(|dir| dir.join(".git").is_dir()) {
+            for dir in ancestors[..=repository].iter().rev() {
+                stack.push(dir)?;
+            }
+        }
+
+        Ok(stack)
+    }
+
+    /// Push the rules defined in `dir` onto the stack
+    ///
+    /// # Errors
+    /// - When reading any of the gitignore files fails
+    pub fn push(&mut self, dir: &Path) -> Result<()> {
+        let gitignore = dir.join(".gitignore");
+        let gitignore = if gitignore.is_file() {
+            Some(parse_gitignore(&gitignore)?)
+        } else {
+            None
+        };
+
+        let gsyncignore = dir.join(".gsyncignore");
+        let gsyncignore = if gsyncignore.is_file() {
+            Some(parse_gitignore(&gsyncignore)?)
+        } else {
+            None
+        };
+
+        let is_repository = dir.join(".git").is_dir();
+        let info_exclude = dir.join(".git").join("info").join("exclude");
+        let info_exclude = if is_repository && info_exclude.is_file() {
+            let contents = read_rules(&info_exclude).with_path(&info_exclude)?;
+            Some(build_rules(dir, &contents, &info_exclude))
+        } else {
+            None
+        };
+
+        self.frames.push(Frame { dir: dir.to_path_buf(), gitignore, gsyncignore, info_exclude, is_repository });
+        Ok(())
+    }
+
+    /// Pop the rules of the innermost directory off the stack
+    pub fn pop(&mut self) {
+        self.frames.pop();
+    }
+
+    /// Check if a path matches one of the configured include patterns
+    pub fn is_included(&self, path: &Path, is_dir: bool) -> bool {
+        path.starts_with(self.include.path()) && self.include.matched(path, is_dir).is_ignore()
+    }
+
+    /// Check if one of the configured include patterns could match an entry below the directory `dir`,
+    /// in which case `dir` has to be traversed even if it is excluded. This errs on the side of traversing
+    pub fn may_include_below(&self, dir: &Path) -> bool {
+        match dir.strip_prefix(self.include.path()) {
+            Ok(relative) => self.include_patterns.iter().any(|pattern| may_match_below(pattern, relative)),
+            Err(_) => false
+        }
+    }
+
+    /// Check if a path is excluded by the configured patterns or the rules on the stack.
+    ///
+    /// The configured include patterns take precedence over everything, followed by the configured exclude patterns and `.gsyncignore` files.
+    /// After that, as in git, rules in `.gitignore` files take precedence over those in `.git/info/exclude`,
+    /// which take precedence over those in git's `core.excludesFile`. Among `.gsyncignore` and `.gitignore` files, the file deepest in the tree takes precedence.
+    pub fn is_excluded(&self, path: &Path, is_dir: bool) -> bool {
+        if self.is_included(path, is_dir) {
+            return false;
+        }
+
+        if path.starts_with(self.exclude.path()) && self.exclude.matched(path, is_dir).is_ignore() {
+            return true;
+        }
+
+        for frame in self.frames.iter().rev() {
+            if let Some(excluded) = frame.gsyncignore.as_ref().and_then(|rules| decide(rules.matched(path, is_dir))) {
+                return excluded;
+            }
+        }
+
+        for frame in self.frames.iter().rev() {
+            if let Some(excluded) = frame.gitignore.as_ref().and_then(|rules| decide(rules.matched(path, is_dir))) {
+                return excluded;
+            }
+        }
+
+        // Outside of a git repository, the outermost directory is treated as the root
+        let repository = match self.frames.iter().rev().find(|frame| frame.is_repository).or_else(|| self.frames.first()) {
+            Some(repository) => repository,
+            None => return false
+        };
+
+        if let Some(excluded) = repository.info_exclude.as_ref().and_then(|rules| decide(rules.matched(path, is_dir))) {
+            return excluded;
+        }
+
+        match path.strip_prefix(&repository.dir) {
+            Ok(relative) => decide(self.global.matched(relative, is_dir)).unwrap_or(false),
+            Err(_) => false
+        }
+    }
+}
+
+/// Check if a gitignore pattern could match an entry below `dir`, which is relative to the directory the pattern is rooted at
+fn may_match_below(pattern: &str, dir: &Path) -> bool {
+    let pattern = pattern.trim();
+    if pattern.is_empty() || pattern.starts_with('#') || pattern.starts_with('!') {
+        return false;
+    }
+
+    // As in git, a pattern without a slash other than a trailing one matches at any depth
+    let pattern = pattern.trim_end_matches('/');
+    if !pattern.contains('/') {
+        return true;
+    }
+
+    let mut components = pattern.trim_start_matches('/').split('/');
+    for dir_component in dir.components() {
+        let dir_component = dir_component.as_os_str().to_str().unwrap_or_default();
+        match components.next() {
+            Some("**") => return true,
+            Some(component) if component == dir_component || component.contains(&['*', '?', '['][..]) => {},
+            // The pattern names another directory, or `dir` itself
+            _ => return false
+        }
+    }
+
+    components.next().is_some()
+}
+
+/// Convert the result of matching a path against a set of rules. Returns `None` if no rule matched the path
+fn decide<T>(matched: Match<T>) -> Option<bool> {
+    match matched {
+        Match::Ignore(_) => Some(true),
+        Match::Whitelist(_) => Some(false),
+        Match::None => None
+    }
+}
+
+/// Parse a gitignore file. The patterns in it are matched relative to the directory containing the file
+///
+/// # Errors
+/// - When reading the file fails, with the path of the file
+fn parse_gitignore(p: &Path) -> Result<Gitignore> {
+    let contents = read_rules(p).with_path(p)?;
+    Ok(build_rules(p.parent().unwrap(), &contents, p))
+}
+
+/// Read the contents of a file with gitignore rules
+///
+/// # Errors
+/// - When reading the file fails, e.g. when it isn't valid UTF-8
+fn read_rules(p: &Path) -> Result<String> {
+    Ok(unwrap_io_err!(fs::read_to_string(p)))
+}
+
+/// Build a set of gitignore rules from the contents of a gitignore file, rooted at `root`.
+/// Invalid patterns are skipped with a warning
+fn build_rules(root: &Path, contents: &str, source: &Path) -> Gitignore {
+    let mut builder = GitignoreBuilder::new(root);
+    for line in contents.lines() {
+        if let Err(e) = builder.add_line(Some(source.to_path_buf()), line) {
+            eprintln!("Warning: Skipping invalid pattern in '{}': {}", source.to_str().unwrap(), e);
+        }
+    }
+
+    builder.build().unwrap_or_else(|e| {
+        eprintln!("Warning: Failed to parse '{}', it will be ignored: {}", source.to_str().unwrap(), e);
+        Gitignore::empty()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{build_rules, may_match_below, ExclusionStack, Frame};
+    use ignore::gitignore::Gitignore;
+    use std::path::Path;
+
+    /// Build a set of rules rooted at `root` from the provided lines
+    fn rules(root: &str, lines: &str) -> Gitignore {
+        build_rules(Path::new(root), lines, &Path::new(root).join(".gitignore"))
+    }
+
+    /// Create a stack from `.gitignore` rules, the first directory being the root of a repository
+    fn stack(frames: &[(&str, &str)]) -> ExclusionStack {
+        let frames = frames.iter().enumerate().map(|(idx, (dir, lines))| Frame {
+            dir:            dir.into(),
+            gitignore:      Some(rules(dir, lines)),
+            gsyncignore:    None,
+            info_exclude:   None,
+            is_repository:  idx == 0
+        }).collect();
+
+        ExclusionStack { include: Gitignore::empty(), include_patterns: Vec::new(), exclude: Gitignore::empty(), global: Gitignore::empty(), frames }
+    }
+
+    #[test]
+    fn glob_matches_at_any_depth() {
+        let exclusions = stack(&[("/repo", "*.log")]);
+
+        assert!(exclusions.is_excluded(Path::new("/repo/debug.log"), false));
+        assert!(exclusions.is_excluded(Path::new("/repo/a/b/debug.log"), false));
+        assert!(!exclusions.is_excluded(Path::new("/repo/debug.txt"), false));
+    }
+
+    #[test]
+    fn double_asterisk() {
+        let exclusions = stack(&[("/repo", "target/**\nsrc/**/generated.rs")]);
+
+        assert!(exclusions.is_excluded(Path::new("/repo/target/debug"), true));
+        assert!(exclusions.is_excluded(Path::new("/repo/src/a/b/generated.rs"), false));
+        assert!(!exclusions.is_excluded(Path::new("/repo/src/main.rs"), false));
+    }
+
+    #[test]
+    fn negation() {
+        let exclusions = stack(&[("/repo", "*.log\n!keep.log")]);
+
+        assert!(exclusions.is_excluded(Path::new("/repo/debug.log"), false));
+        assert!(!exclusions.is_excluded(Path::new("/repo/keep.log"), false));
+    }
+
+    #[test]
+    fn anchoring() {
+        let exclusions = stack(&[("/repo", "build\n/dist\ncache/")]);
+
+        assert!(exclusions.is_excluded(Path::new("/repo/build"), true));
+        assert!(exclusions.is_excluded(Path::new("/repo/a/build"), true));
+        assert!(exclusions.is_excluded(Path::new("/repo/dist"), true));
+        assert!(!exclusions.is_excluded(Path::new("/repo/a/dist"), true));
+        assert!(exclusions.is_excluded(Path::new("/repo/cache"), true));
+        assert!(!exclusions.is_excluded(Path::new("/repo/cache"), false));
+    }
+
+    #[test]
+    fn deeper_rules_take_precedence() {
+        let exclusions = stack(&[("/repo", "*.tmp"), ("/repo/a", "*.txt\n!keep.tmp")]);
+
+        assert!(exclusions.is_excluded(Path::new("/repo/a/notes.txt"), false));
+        assert!(!exclusions.is_excluded(Path::new("/repo/a/keep.tmp"), false));
+        assert!(exclusions.is_excluded(Path::new("/repo/a/other.tmp"), false));
+    }
+
+    #[test]
+    fn popped_rules_no_longer_apply() {
+        let mut exclusions = stack(&[("/repo", "*.tmp"), ("/repo/a", "*.txt")]);
+        exclusions.pop();
+
+        assert!(!exclusions.is_excluded(Path::new("/repo/b/notes.txt"), false));
+        assert!(exclusions.is_excluded(Path::new("/repo/b/keep.tmp"), false));
+    }
+
+    #[test]
+    fn gitignore_takes_precedence_over_info_exclude_and_global() {
+        let mut exclusions = stack(&[("/repo", "!keep.log\n!keep.bak")]);
+        exclusions.frames[0].info_exclude = Some(rules("/repo", "*.log\nother.log"));
+        exclusions.global = rules("", "*.bak\nglobal.txt");
+
+        assert!(!exclusions.is_excluded(Path::new("/repo/keep.log"), false));
+        assert!(exclusions.is_excluded(Path::new("/repo/other.log"), false));
+        assert!(!exclusions.is_excluded(Path::new("/repo/keep.bak"), false));
+        assert!(exclusions.is_excluded(Path::new("/repo/a/global.txt"), false));
+    }
+
+    #[test]
+    fn gsyncignore_takes_precedence_over_gitignore() {
+        let mut exclusions = stack(&[("/repo", "*.env\n!fixtures/"), ("/repo/a", "")]);
+        exclusions.frames[0].gsyncignore = Some(rules("/repo", "!keep.env\nfixtures/"));
+
+        assert!(!exclusions.is_excluded(Path::new("/repo/a/keep.env"), false));
+        assert!(exclusions.is_excluded(Path::new("/repo/a/other.env"), false));
+        assert!(exclusions.is_excluded(Path::new("/repo/fixtures"), true));
+    }
+
+    #[test]
+    fn include_patterns_below_excluded_directories() {
+        assert!(may_match_below("build/keep.txt", Path::new("build")));
+        assert!(may_match_below("/build/*/keep.txt", Path::new("build/debug")));
+        assert!(may_match_below("build/**", Path::new("build/a/b")));
+        assert!(may_match_below("*.env", Path::new("a/b")));
+        assert!(may_match_below("keep/", Path::new("a")));
+
+        assert!(!may_match_below("build/keep.txt", Path::new("target")));
+        assert!(!may_match_below("build/keep.txt", Path::new("build/keep.txt")));
+        assert!(!may_match_below("!build/keep.txt", Path::new("build")));
+        assert!(!may_match_below("", Path::new("build")));
+    }
+
+    #[test]
+    fn unreadable_gitignore_is_named() {
+        let dir = std::env::temp_dir().join(format!("gsync-bad-gitignore-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(".gitignore"), b"\xff\xfe").unwrap();
+
+        let mut exclusions = stack(&[]);
+        let error = exclusions.push(&dir).unwrap_err();
+        assert!(error.report().contains(&format!("path '{}'", dir.join(".gitignore").to_str().unwrap())), "{}", error.report());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn configured_patterns_take_precedence() {
+        let mut exclusions = stack(&[("/repo", "*.env\n!*.bin")]);
+        exclusions.frames[0].gsyncignore = Some(rules("/repo", "!secret.env"));
+        exclusions.include = rules("/repo", "local.env");
+        exclusions.exclude = rules("/repo", "*.bin\nsecret.env");
+
+        assert!(!exclusions.is_excluded(Path::new("/repo/a/local.env"), false));
+        assert!(exclusions.is_excluded(Path::new("/repo/a/other.env"), false));
+        assert!(exclusions.is_excluded(Path::new("/repo/blob.bin"), false));
+        assert!(exclusions.is_excluded(Path::new("/repo/secret.env"), false));
+    }
+}
@@ -0,0 +1,909 @@
+//! Module related to syncing files
+
+mod state;
+mod exclusions;
+mod two_way;
+mod workers;
+
+use crate::config::Configuration;
+use crate::env::Env;
+use crate::{Result, Error};
+use crate::error::ResultExt;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::fs;
+use crate::{unwrap_other_err, unwrap_io_err};
+use crate::api::drive;
+use crate::backend::{Backend, PARTIAL_DOWNLOAD_EXTENSION};
+use reqwest::StatusCode;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::SystemTime;
+use state::SyncState;
+use exclusions::ExclusionStack;
+use workers::Workers;
+
+/// Options controlling the behaviour of a sync run
+#[derive(Debug, Clone, Default)]
+pub struct SyncOptions {
+    /// Remove entries from Google Drive which no longer exist locally, or which are now ignored.
+    /// In a two-way sync, also remove local files which no longer exist in Google Drive
+    pub delete:     bool,
+
+    /// Permanently delete removed entries, rather than moving them to the trash
+    pub permanent:  bool,
+
+    /// Only compare the local files against Google Drive and report what would change, without changing anything
+    pub dry_run:    bool,
+
+    /// Also pull changes made in Google Drive to the local files
+    pub two_way:    bool,
+
+    /// The maximum number of files to upload or update at the same time. Files are synced one by one if this is 1 or less
+    pub jobs:       usize,
+
+    /// Record entries which fail to sync and continue with the rest, instead of stopping at the first failure
+    pub continue_on_error:  bool,
+
+    /// Sync the contents of the input directories straight into the root folder, rather than into a folder named after each directory.
+    /// Used for sync jobs, whose destination folder stands for the source directory
+    pub into_root:          bool
+}
+
+/// Struct describing what was, or in a dry run would be, changed during a sync run
+#[derive(Debug, Default)]
+struct SyncReport {
+    /// Local paths of the directories for which a folder was created
+    created:    Vec<PathBuf>,
+
+    /// Local paths of the files which were uploaded as new files
+    uploaded:   Vec<PathBuf>,
+
+    /// Local paths of the files of which the existing copy was updated
+    updated:    Vec<PathBuf>,
+
+    /// Local paths of the entries which were removed from Google Drive
+    deleted:    Vec<PathBuf>,
+
+    /// Local paths of the files and directories which were downloaded from Google Drive
+    downloaded: Vec<PathBuf>,
+
+    /// Local paths of the entries which were removed locally, because they were removed from Google Drive
+    removed:    Vec<PathBuf>,
+
+    /// Local paths of the files which were changed both locally and in Google Drive, with the path the copy from Google Drive was saved to
+    conflicts:  Vec<(PathBuf, PathBuf)>,
+
+    /// Local paths of the entries which failed to sync, with the reason. Only used when continuing on errors
+    failed:     Vec<(PathBuf, String)>
+}
+
+/// Enum describing what happened to a single file during a sync run
+#[derive(Debug)]
+enum FileOutcome {
+    /// The file was already up-to-date
+    Unchanged,
+
+    /// The file was uploaded as a new file
+    Uploaded(PathBuf),
+
+    /// The existing copy of the file was updated
+    Updated(PathBuf)
+}
+
+impl SyncReport {
+    /// Record what happened to a file
+    fn record(&mut self, outcome: FileOutcome) {
+        match outcome {
+            FileOutcome::Unchanged => {},
+            FileOutcome::Uploaded(path) => self.uploaded.push(path),
+            FileOutcome::Updated(path) => self.updated.push(path)
+        }
+    }
+
+    /// Record that an entry failed to sync
+    fn fail(&mut self, path: PathBuf, error: Error) {
+        let reason = error.report();
+        eprintln!("Error: Failed to sync '{}': {}", path.to_str().unwrap(), reason);
+        self.failed.push((path, reason));
+    }
+
+    /// Print the entries which failed to sync
+    fn print_failed(&self) {
+        eprintln!("Error: {} entries failed to sync:", self.failed.len());
+        for (path, reason) in &self.failed {
+            eprintln!("  {}: {}", path.to_str().unwrap(), reason);
+        }
+    }
+
+    /// Print the planned changes of a dry run
+    fn print_plan(&self) {
+        println!("Info: Dry run complete, nothing was changed. Planned changes:");
+        let actions = [
+            ("create", &self.created),
+            ("upload", &self.uploaded),
+            ("update", &self.updated),
+            ("delete", &self.deleted),
+            ("download", &self.downloaded),
+            ("remove", &self.removed)
+        ];
+
+        for (action, paths) in actions {
+            for path in paths {
+                println!("  {:<10}{}", action, path.to_str().unwrap());
+            }
+        }
+
+        for (path, _) in &self.conflicts {
+            println!("  {:<10}{}", "conflict", path.to_str().unwrap());
+        }
+
+        println!("Info: {} folders to create, {} files to upload, {} files to update, {} entries to delete", self.created.len(), self.uploaded.len(), self.updated.len(), self.deleted.len());
+        if !self.downloaded.is_empty() || !self.removed.is_empty() || !self.conflicts.is_empty() {
+            println!("Info: {} entries to download, {} entries to remove locally, {} conflicts", self.downloaded.len(), self.removed.len(), self.conflicts.len());
+        }
+    }
+
+    /// Print the entries removed from the backend called `backend`, and in a two-way sync the entries removed locally
+    fn print_deleted(&self, options: &SyncOptions, backend: &str) {
+        if self.deleted.is_empty() {
+            println!("Info: Nothing was removed from {}.", backend);
+        } else {
+            println!("Info: Removed {} entries from {}:", self.deleted.len(), backend);
+            for path in &self.deleted {
+                println!("  {}", path.to_str().unwrap());
+            }
+        }
+
+        if options.two_way && !self.removed.is_empty() {
+            println!("Info: Removed {} local entries which were removed from {}:", self.removed.len(), backend);
+            for path in &self.removed {
+                println!("  {}", path.to_str().unwrap());
+            }
+        }
+    }
+
+    /// Print the changes pulled from the backend called `backend` and the conflicts encountered in a two-way sync
+    fn print_pulled(&self, backend: &str) {
+        println!("Info: Downloaded {} entries from {}.", self.downloaded.len(), backend);
+        if !self.conflicts.is_empty() {
+            println!("Warning: {} files were changed both locally and in {}. The local version was uploaded, the other version was saved next to it:", self.conflicts.len(), backend);
+            for (path, conflict_path) in &self.conflicts {
+                println!("  {} -> {}", path.to_str().unwrap(), conflict_path.to_str().unwrap());
+            }
+        }
+    }
+}
+
+/// A boxed future, needed for recursive async functions
+pub(crate) type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
+
+/// Struct describing everything shared by the steps of a sync run
+struct SyncContext<'a> {
+    /// Env instance
+    env:        &'a Env,
+
+    /// The backend files are synced to
+    backend:    &'a Arc<dyn Backend>,
+
+    /// The options of this run
+    options:    &'a SyncOptions,
+
+    /// The state of all entries as of the previous sync, by local path
+    known:              HashMap<PathBuf, SyncState>,
+
+    /// In a two-way sync, the IDs of the folders in Google Drive whose contents changed since the previous sync.
+    /// `None` if this isn't known, in which case every folder has to be listed
+    changed_folders:    Option<HashSet<String>>,
+
+    /// The workers files are synced on, if files are synced concurrently
+    workers:            Option<Workers>,
+
+    /// What was changed during this run
+    report:             SyncReport
+}
+
+impl SyncContext<'_> {
+    /// Get the stored state of a local path, if it was previously synced as a child of the folder `parent_id`
+    fn known(&self, path: &Path, parent_id: &str, is_dir: bool) -> Option<&SyncState> {
+        self.known.get(path).filter(|state| state.is_dir == is_dir && state.parent_id == parent_id)
+    }
+
+    /// Forget the stored state of a local path, and of everything below it
+    ///
+    /// # Errors
+    /// - When a database operation fails
+    fn forget(&mut self, path: &Path) -> Result<()> {
+        self.known.retain(|known, _| !known.starts_with(path));
+        state::remove(self.env, path)
+    }
+
+    /// Handle the result of syncing the entry at `path`, adding the path to a failure. When continuing on errors, a failure is recorded and the sync continues
+    ///
+    /// # Errors
+    /// - The failure, when not continuing on errors
+    fn check(&mut self, path: PathBuf, result: Result<()>) -> Result<()> {
+        match result.with_path(&path) {
+            Err(e) if self.options.continue_on_error => {
+                self.report.fail(path, e);
+                Ok(())
+            },
+            result => result
+        }
+    }
+}
+
+/// Sync the configured input files to google drive. Returns the number of entries which failed to sync,
+/// which can only be more than zero when continuing on errors
+///
+/// # Errors
+/// - When traversing the input files fails, unless continuing on errors
+/// - When an entry fails to sync, unless continuing on errors
+pub async fn sync(config: &Configuration, env: &Env, backend: &Arc<dyn Backend>, options: &SyncOptions) -> Result<usize> {
+    // Unwrap is safe because the caller verifiers the configuration
+    let input = config.input_files.as_ref().unwrap();
+
+    // An input which no longer exists is skipped, so with `--delete` its copy in the root folder is removed
+    let mut input_parts = Vec::new();
+    for f in input.split(',') {
+        match normalize_path(f) {
+            Ok(path) => input_parts.push(path),
+            Err(e) => eprintln!("Warning: Input '{}' can't be found, skipping it: {}", f, e)
+        }
+    }
+
+    println!("Info: Loading sync state from database");
+    let known = state::load_all(env)?;
+
+    let (changed_folders, page_token) = if options.two_way && !env.root_folder.is_empty() {
+        two_way::changed_folders(env, backend.as_ref(), &known).await?
+    } else {
+        (None, None)
+    };
+
+    let mut ctx = SyncContext {
+        env,
+        backend,
+        options,
+        known,
+        changed_folders,
+        workers:    if options.jobs > 1 { Some(Workers::new(options.jobs)) } else { None },
+        report:     SyncReport::default()
+    };
+
+    let mut children = Vec::new();
+    let mut input_names = Vec::new();
+    for input in input_parts {
+        let name = input.clone();
+        let name = name.to_str().unwrap();
+        println!("Info: Traversing file tree for input '{}'", name);
+
+        // The copy of an input which can't be traversed is left alone, so it's still counted as an input
+        let mut exclusions = match ExclusionStack::new(&input, config.include_patterns.as_deref(), config.exclude_patterns.as_deref()) {
+            Ok(exclusions) => exclusions,
+            Err(e) => {
+                ctx.check(input.clone(), Err(e))?;
+                input_names.push(input.file_name().unwrap().to_str().unwrap().to_string());
+                continue;
+            }
+        };
+        let ichildren = traverse(&mut ctx, input.clone(), &mut exclusions, false)?;
+
+        let mut child_count = 0i64;
+        for child in ichildren.iter() {
+            child_count += child.count_all_children();
+        }
+        println!("Info: Found {} child nodes for input '{}'.", child_count, name);
+
+        // Traversing leaves the stack as it was, so it can be used again while syncing
+        input_names.extend(ichildren.iter().map(|child| child.name().to_string()));
+        children.push((ichildren, exclusions));
+    }
+
+    println!("Info: All directories traversed. Beginning sync now.");
+
+    for (ichildren, mut exclusions) in children {
+        for child in ichildren {
+            match child {
+                // The root folder doesn't exist yet, which can only be the case in a dry run
+                Child::Directory(dir) if options.into_root && env.root_folder.is_empty() => {
+                    for child in &dir.children {
+                        plan_new_child(child, &mut ctx.report);
+                    }
+                },
+                child if env.root_folder.is_empty() => plan_new_child(&child, &mut ctx.report),
+                Child::Directory(dir) if options.into_root => sync_into_root(&mut ctx, dir, &env.root_folder, &mut exclusions).await?,
+                child => sync_child(&mut ctx, child, &env.root_folder, &mut exclusions).await?
+            }
+        }
+    }
+
+    // The root folder of a sync job stands for its source directory, of which the removed entries were handled while syncing it
+    if options.delete && !options.into_root && !env.root_folder.is_empty() {
+        delete_removed_inputs(&mut ctx, &input_names, &env.root_folder).await?;
+    }
+
+    if let Some(workers) = ctx.workers.take() {
+        workers.join(&mut ctx.report, options.continue_on_error).await?;
+    }
+
+    if options.dry_run {
+        ctx.report.print_plan();
+        if !ctx.report.failed.is_empty() {
+            ctx.report.print_failed();
+        }
+
+        return Ok(ctx.report.failed.len());
+    }
+
+    // Only saved now the sync succeeded, so the changes are listed again if it didn't
+    if let (Some(page_token), true) = (page_token, ctx.report.failed.is_empty()) {
+        state::save_page_token(env, &page_token)?;
+    }
+
+    if options.delete {
+        ctx.report.print_deleted(options, &backend.describe());
+    }
+
+    if options.two_way {
+        ctx.report.print_pulled(&backend.describe());
+    }
+
+    if !ctx.report.failed.is_empty() {
+        ctx.report.print_failed();
+    }
+
+    Ok(ctx.report.failed.len())
+}
+
+/// Remove an entry from Google Drive, moving it to the trash unless permanent deletion was requested
+///
+/// # Errors
+/// - When a request to Google fails
+/// - When a database operation fails
+async fn remove_remote(ctx: &mut SyncContext<'_>, path: PathBuf, id: &str) -> Result<()> {
+    if ctx.options.dry_run {
+        ctx.report.deleted.push(path);
+        return Ok(());
+    }
+
+    if ctx.options.permanent {
+        println!("Info: Deleting '{}' from {}", path.to_str().unwrap(), ctx.backend.describe());
+        ctx.backend.delete_file(id).await.with_file_id(id)?;
+    } else {
+        println!("Info: Moving '{}' to the trash in {}", path.to_str().unwrap(), ctx.backend.describe());
+        ctx.backend.trash_file(id).await.with_file_id(id)?;
+    }
+
+    ctx.forget(&path)?;
+    ctx.report.deleted.push(path);
+    Ok(())
+}
+
+/// Remove all entries from a folder in Google Drive which do not have a counterpart in `dir` anymore,
+/// either because they were removed locally or because they are now ignored
+///
+/// # Errors
+/// - When a request to Google fails
+/// - When a database operation fails
+async fn delete_removed(ctx: &mut SyncContext<'_>, dir: &Directory, folder_id: &str) -> Result<()> {
+    let remote_children = ctx.backend.list_folder(folder_id).await?;
+    for remote in remote_children {
+        if dir.children.iter().any(|child| child.name() == remote.name) {
+            continue;
+        }
+
+        let path = dir.path.join(&remote.name);
+        let result = remove_remote(ctx, path.clone(), &remote.id).await;
+        ctx.check(path, result)?;
+    }
+
+    Ok(())
+}
+
+/// Remove all entries from the root folder in Google Drive which aren't one of the inputs anymore,
+/// either because the input was removed locally or because it is no longer configured.
+/// In a two-way sync, only entries which were synced before are removed, since anything else was added in Google Drive
+///
+/// # Errors
+/// - When a request to Google fails
+/// - When a database operation fails
+async fn delete_removed_inputs(ctx: &mut SyncContext<'_>, input_names: &[String], root_id: &str) -> Result<()> {
+    let remote_children = ctx.backend.list_folder(root_id).await?;
+    for remote in remote_children {
+        if input_names.contains(&remote.name) {
+            continue;
+        }
+
+        let known_path = ctx.known.values()
+            .find(|state| state.drive_id == remote.id && state.parent_id == root_id)
+            .map(|state| state.path.clone());
+
+        let path = match known_path {
+            Some(path) => path,
+            None if ctx.options.two_way => continue,
+            None => PathBuf::from(&remote.name)
+        };
+
+        let result = remove_remote(ctx, path.clone(), &remote.id).await;
+        ctx.check(path, result)?;
+    }
+
+    Ok(())
+}
+
+/// Record the creation of a folder which doesn't exist in Google Drive yet, and of everything below it, in a dry run
+fn plan_new_child(child: &Child, report: &mut SyncReport) {
+    match child {
+        Child::Directory(dir) => {
+            report.created.push(dir.path.clone());
+            for child in &dir.children {
+                plan_new_child(child, report);
+            }
+        },
+        Child::File(file_path) => report.uploaded.push(file_path.clone()),
+        Child::Untraversed(_) => {}
+    }
+}
+
+/// Sync a child with Google Drive. This is a recursive function
+///
+/// When continuing on errors, a failure is recorded and doesn't stop the sync of the child's siblings
+fn sync_child<'a>(ctx: &'a mut SyncContext<'_>, child: Child, parent_id: &'a str, exclusions: &'a mut ExclusionStack) -> BoxFuture<'a, Result<()>> {
+    Box::pin(async move {
+        let path = child.path().to_path_buf();
+        let result = sync_entry(ctx, child, parent_id, exclusions).await;
+        ctx.check(path, result)
+    })
+}
+
+/// Sync the children of the input directory `dir` into the root folder, which stands for `dir` itself
+///
+/// # Errors
+/// - When a request to Google fails
+/// - When a database operation fails
+async fn sync_into_root(ctx: &mut SyncContext<'_>, dir: Directory, root_id: &str, exclusions: &mut ExclusionStack) -> Result<()> {
+    exclusions.push(&dir.path)?;
+
+    let result = if ctx.options.two_way {
+        two_way::sync_directory(ctx, dir, root_id, exclusions).await
+    } else {
+        sync_children(ctx, dir, root_id, exclusions, false).await
+    };
+
+    exclusions.pop();
+    result
+}
+
+/// Sync a single child with Google Drive, and everything below it
+///
+/// Entries whose local state matches the stored state are skipped without querying Drive.
+/// The rules of every directory are pushed onto `exclusions` while syncing its children
+///
+/// # Errors
+/// - When a request to Google fails
+/// - When a database operation fails
+/// - When reading a file fails
+async fn sync_entry(ctx: &mut SyncContext<'_>, child: Child, parent_id: &str, exclusions: &mut ExclusionStack) -> Result<()> {
+    let (env, backend, options) = (ctx.env, ctx.backend, ctx.options);
+    match child {
+        Child::Directory(dir) => {
+            let (folder_id, created) = match ctx.known(&dir.path, parent_id, true) {
+                Some(state) => (state.drive_id.clone(), false),
+                None => {
+                    println!("Info: Querying {} for directory '{}'", backend.describe(), &dir.name);
+                    let query_result = backend.find(parent_id, &dir.name, true).await?;
+
+                    let (id, created) = match query_result.into_iter().last() {
+                        Some(file) => (file.id, false),
+                        None if options.dry_run => {
+                            plan_new_child(&Child::Directory(dir), &mut ctx.report);
+                            return Ok(());
+                        },
+                        None => {
+                            println!("Info: Creating directory '{}'", &dir.name);
+                            ctx.report.created.push(dir.path.clone());
+                            (backend.create_folder(&dir.name, parent_id).await?, true)
+                        }
+                    };
+
+                    if !options.dry_run {
+                        state::save(env, &SyncState {
+                            path:       dir.path.clone(),
+                            drive_id:   id.clone(),
+                            parent_id:  parent_id.to_string(),
+                            is_dir:     true,
+                            size:       0,
+                            mtime:      0,
+                            checksum:   None
+                        })?;
+                    }
+
+                    (id, created)
+                }
+            };
+
+            exclusions.push(&dir.path)?;
+
+            // A folder we've just created can't contain anything that should be removed or pulled
+            let result = if options.two_way && !created {
+                two_way::sync_directory(ctx, dir, &folder_id, exclusions).await
+            } else {
+                sync_children(ctx, dir, &folder_id, exclusions, created).await
+            };
+
+            // Also popped on failure, as the sync may continue with the next directory
+            exclusions.pop();
+            result?;
+        },
+        Child::File(file_path) => {
+            let state = ctx.known(&file_path, parent_id, false).cloned();
+            match &mut ctx.workers {
+                Some(workers) => workers.spawn(env, backend, options, file_path, parent_id, state).await?,
+                None => {
+                    let outcome = sync_file(env, backend.as_ref(), options, file_path, parent_id, state).await?;
+                    ctx.report.record(outcome);
+                }
+            }
+        },
+        // Its failure was already recorded while traversing
+        Child::Untraversed(_) => {}
+    }
+
+    Ok(())
+}
+
+/// Sync the children of a directory one-way, removing entries from Google Drive which no longer exist locally if requested.
+/// Nothing is removed from a folder which was just `created`
+///
+/// # Errors
+/// - When a request to Google fails
+/// - When a database operation fails
+async fn sync_children(ctx: &mut SyncContext<'_>, dir: Directory, folder_id: &str, exclusions: &mut ExclusionStack, created: bool) -> Result<()> {
+    if ctx.options.delete && !created {
+        delete_removed(ctx, &dir, folder_id).await?;
+    }
+
+    for child in dir.children {
+        sync_child(ctx, child, folder_id, exclusions).await?;
+    }
+
+    Ok(())
+}
+
+/// Sync a file to Google Drive. `state` is the stored state of the file, if it was previously synced as a child of `parent_id`
+///
+/// This doesn't touch the `SyncContext`, so it can run on a worker thread
+///
+/// # Errors
+/// - When a request to Google fails
+/// - When a database operation fails
+/// - When reading the file fails
+async fn sync_file(env: &Env, backend: &dyn Backend, options: &SyncOptions, file_path: PathBuf, parent_id: &str, state: Option<SyncState>) -> Result<FileOutcome> {
+    let file_name = file_path.file_name().unwrap().to_str().unwrap();
+    let (size, mtime) = get_size_and_modification_time(&file_path)?;
+
+    // The modification time is only used as a cheap pre-filter, the decision to upload is made on size and checksum
+    if let Some(state) = &state {
+        if state.size == size && state.mtime == mtime {
+            println!("Info: File '{}' is up-to-date.", file_name);
+            return Ok(FileOutcome::Unchanged);
+        }
+    }
+
+    let checksum = md5_checksum(&file_path)?;
+    let (drive_id, outcome) = match state {
+        Some(state) if state.size == size && state.checksum.as_deref() == Some(checksum.as_str()) => {
+            println!("Info: File '{}' is up-to-date.", file_name);
+            (state.drive_id, FileOutcome::Unchanged)
+        },
+        Some(_) if options.dry_run => return Ok(FileOutcome::Updated(file_path)),
+        Some(state) => {
+            println!("Info: Updating file '{}'", file_name);
+            match backend.update_file(&file_path, &state.drive_id).await.with_file_id(&state.drive_id) {
+                Ok(_) => (state.drive_id, FileOutcome::Updated(file_path.clone())),
+                Err(e) if e.status() == Some(StatusCode::NOT_FOUND) => {
+                    println!("Info: File '{}' no longer exists in {}. Uploading it again.", file_name, backend.describe());
+                    state::remove(env, &file_path)?;
+                    (backend.upload_file(&file_path, parent_id).await?, FileOutcome::Uploaded(file_path.clone()))
+                },
+                Err(e) => return Err(e)
+            }
+        },
+        None => {
+            println!("Info: Querying {} for file '{}'", backend.describe(), file_name);
+            let query_result = backend.find(parent_id, file_name, false).await?;
+
+            match query_result.first() {
+                Some(file) => {
+                    if remote_file_changed(backend, &file_path, file, size, &checksum).await? {
+                        if options.dry_run {
+                            return Ok(FileOutcome::Updated(file_path));
+                        }
+
+                        println!("Info: Updating file '{}'", file_name);
+                        backend.update_file(&file_path, &file.id).await.with_file_id(&file.id)?;
+                        (file.id.clone(), FileOutcome::Updated(file_path.clone()))
+                    } else {
+                        println!("Info: File '{}' is up-to-date.", file_name);
+                        (file.id.clone(), FileOutcome::Unchanged)
+                    }
+                },
+                None if options.dry_run => return Ok(FileOutcome::Uploaded(file_path)),
+                None => {
+                    println!("Info: Uploading file '{}'", file_name);
+                    (backend.upload_file(&file_path, parent_id).await?, FileOutcome::Uploaded(file_path.clone()))
+                }
+            }
+        }
+    };
+
+    if !options.dry_run {
+        state::save(env, &SyncState {
+            path:       file_path.clone(),
+            drive_id,
+            parent_id:  parent_id.to_string(),
+            is_dir:     false,
+            size,
+            mtime,
+            checksum:   Some(checksum)
+        })?;
+    }
+
+    Ok(outcome)
+}
+
+/// Get the size in bytes and the modification time of a file, in seconds since the epoch
+///
+/// # Errors
+/// - When the underlying IO operation to fetch the metadata fails
+fn get_size_and_modification_time(path: &Path) -> Result<(i64, i64)> {
+    let meta = unwrap_io_err!(path.metadata());
+    let meta_modified = unwrap_io_err!(meta.modified());
+    let as_epoch = unwrap_other_err!(meta_modified.duration_since(SystemTime::UNIX_EPOCH)).as_secs();
+
+    Ok((meta.len() as i64, as_epoch as i64))
+}
+
+/// Calculate the MD5 checksum of a file's contents, as a lowercase hexadecimal String
+///
+/// # Errors
+/// - When reading the file fails
+pub fn md5_checksum(path: &Path) -> Result<String> {
+    use md5::Digest;
+
+    let mut file = unwrap_io_err!(fs::File::open(path));
+    let mut hasher = md5::Md5::new();
+    unwrap_io_err!(std::io::copy(&mut file, &mut hasher));
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Get the modification time of a file
+///
+/// # Errors
+/// - When the underlying IO operation to fetch the modification time fails
+fn get_modification_time(path: &Path) -> Result<u64> {
+    let meta = unwrap_io_err!(path.metadata());
+    let meta_modified = unwrap_io_err!(meta.modified());
+    let as_epoch = unwrap_other_err!(meta_modified.duration_since(SystemTime::UNIX_EPOCH)).as_secs();
+
+    Ok(as_epoch)
+}
+
+/// Check if a local file differs from its counterpart in Google Drive.
+/// Files are compared by size and MD5 checksum, the checksum is only fetched when the sizes are equal.
+/// If Drive doesn't provide these, e.g. for Google Docs, the modification time is compared instead
+///
+/// # Errors
+/// - When the modification time returned by Google can't be parsed
+/// - When the underlying IO operation to fetch the modification time fails
+/// - When the backend fails to compute the checksum
+async fn remote_file_changed(backend: &dyn Backend, path: &Path, remote: &drive::File, size: i64, checksum: &str) -> Result<bool> {
+    let remote_checksum = match remote.size {
+        Some(remote_size) if remote_size != size => return Ok(true),
+        Some(_) => backend.checksum(remote).await?,
+        None => None
+    };
+
+    match (remote.size, remote_checksum) {
+        (Some(remote_size), Some(remote_checksum)) => Ok(remote_size != size || remote_checksum != checksum),
+        _ => {
+            let mod_time_epoch = unwrap_other_err!(chrono::DateTime::parse_from_rfc3339(&remote.modified_time)).timestamp();
+            file_changed(path, mod_time_epoch)
+        }
+    }
+}
+
+/// Check if a file has changed by their modification time
+///
+/// # Errors
+/// - When the underlying IO operation to fetch the modification time fails
+fn file_changed(path: &Path, stored_modification_time: i64) -> Result<bool> {
+    let modification_time = get_modification_time(path)?;
+    if modification_time > (stored_modification_time as u64) {
+        Ok(true)
+    } else {
+        Ok(false)
+    }
+}
+
+/// Struct describing a Directory
+#[derive(Debug)]
+pub struct Directory {
+    /// The name of the directory
+    name:       String,
+
+    /// The path to the directory
+    path:       PathBuf,
+
+    /// A vector of Child's that this directory is the parent of
+    children:   Vec<Child>
+}
+
+/// Enum describing a Child
+#[derive(Debug)]
+pub enum Child {
+    /// Directory
+    Directory(Directory),
+
+    /// File
+    File(PathBuf),
+
+    /// Directory which couldn't be traversed, when continuing on errors. Nothing below it is synced, and its copy is left alone
+    Untraversed(PathBuf)
+}
+
+impl Child {
+    /// Get the file or directory name of this Child
+    fn name(&self) -> &str {
+        match self {
+            Self::File(path) | Self::Untraversed(path) => path.file_name().unwrap().to_str().unwrap(),
+            Self::Directory(d) => &d.name
+        }
+    }
+
+    /// Get the local path of this Child
+    fn path(&self) -> &Path {
+        match self {
+            Self::File(path) | Self::Untraversed(path) => path,
+            Self::Directory(d) => &d.path
+        }
+    }
+
+    /// Cound all Child elements to this Child
+    fn count_all_children(&self) -> i64 {
+        match self {
+            Self::File(_) => 1,
+            Self::Untraversed(_) => 0,
+            Self::Directory(d) => {
+                let mut count = 0i64;
+                for child in d.children.iter() {
+                    count += child.count_all_children();
+                }
+
+                count
+            }
+        }
+    }
+}
+
+/// Traverse a path to map them to a Vec of Child
+///
+/// The rules defined in a directory are pushed onto `exclusions` when entering it, and popped again when leaving it.
+/// An excluded directory is only traversed if an include pattern could match something below it, `excluded` is set then.
+/// When continuing on errors, a directory which can't be read, or of which an ignore file can't be read, is recorded as a failure and skipped
+///
+/// # Errors
+/// - When reading a directory or ignore file fails, unless continuing on errors
+fn traverse(ctx: &mut SyncContext<'_>, p: PathBuf, exclusions: &mut ExclusionStack, excluded: bool) -> Result<Vec<Child>> {
+    let mut top_children = Vec::new();
+
+    println!("Info: Traversing '{}'", p.to_str().unwrap());
+
+    // Left behind by a download which was interrupted
+    if p.extension().map(|extension| extension == PARTIAL_DOWNLOAD_EXTENSION).unwrap_or(false) {
+        return Ok(vec![]);
+    }
+
+    if p.is_dir() {
+        if p.file_name().unwrap().eq(".git") {
+           return Ok(vec![]);
+        }
+
+        let children = match traverse_directory(ctx, &p, exclusions, excluded) {
+            Ok(children) => children,
+            Err(e) => {
+                ctx.check(p.clone(), Err(e))?;
+                return Ok(vec![Child::Untraversed(p)]);
+            }
+        };
+
+        top_children.push(Child::Directory(Directory { path: p.clone(), name: p.file_name().unwrap().to_str().unwrap().to_string(), children }))
+    } else {
+        top_children.push(Child::File(p));
+    }
+
+    Ok(top_children)
+}
+
+/// Traverse the children of the directory `p`, with its rules pushed onto `exclusions`. Below an `excluded` directory, only included entries are kept
+///
+/// # Errors
+/// - When reading the directory or one of its ignore files fails
+fn traverse_directory(ctx: &mut SyncContext<'_>, p: &Path, exclusions: &mut ExclusionStack, excluded: bool) -> Result<Vec<Child>> {
+    exclusions.push(p)?;
+
+    let result = (|| {
+        let mut children = Vec::new();
+        for entry in unwrap_io_err!(fs::read_dir(p)) {
+            let entry = unwrap_io_err!(entry);
+            let path = entry.path();
+            let is_dir = path.is_dir();
+
+            let excluded = if excluded {
+                !exclusions.is_included(&path, is_dir)
+            } else {
+                exclusions.is_excluded(&path, is_dir)
+            };
+
+            if excluded && !(is_dir && exclusions.may_include_below(&path)) { continue }
+
+            let mut ichild = traverse(ctx, path, exclusions, excluded)?;
+
+            // An excluded directory is only synced for the included entries below it
+            if excluded {
+                ichild.retain(|child| !matches!(child, Child::Directory(dir) if dir.children.is_empty()));
+            }
+
+            children.append(&mut ichild);
+        }
+
+        Ok(children)
+    })();
+
+    // Also popped on failure, as traversing may continue with the next directory
+    exclusions.pop();
+    result
+}
+
+/// Normalize a path. Meaning a relative path will be turned into an absolute one.
+fn normalize_path(i: &str) -> anyhow::Result<PathBuf> {
+    let npath = std::fs::canonicalize(i)?;
+    Ok(npath)
+}
+
+#[cfg(test)]
+mod test {
+    use super::normalize_path;
+    use std::path::PathBuf;
+
+    /// Get the current working directory
+    fn pwd() -> PathBuf {
+        std::env::current_dir().unwrap()
+    }
+
+    #[test]
+    fn normalize_path_relative_period() {
+        let pwd = pwd();
+        let p = "./src";
+
+        assert_eq!(pwd.join("src"), normalize_path(p).unwrap())
+    }
+
+    #[test]
+    fn normalize_path_relative_no_period() {
+        let pwd = pwd();
+        let p = "src";
+
+        assert_eq!(pwd.join(p), normalize_path(p).unwrap())
+    }
+
+    #[test]
+    fn normalize_path_absolute() {
+        let p = "/tmp";
+
+        assert_eq!(PathBuf::from(p), normalize_path(p).unwrap())
+    }
+}
@@ -0,0 +1,134 @@
+//! Module for persisting the last synced state of local files and directories
+
+use crate::env::Env;
+use rusqlite::named_params;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use crate::{Result, unwrap_db_err};
+
+/// Struct describing the state of a local file or directory as it was when it was last synced
+#[derive(Debug, Clone)]
+pub struct SyncState {
+    /// The absolute local path
+    pub path:       PathBuf,
+
+    /// The ID of the file or folder in Google Drive
+    pub drive_id:   String,
+
+    /// The ID of the parent folder in Google Drive
+    pub parent_id:  String,
+
+    /// Whether this entry is a directory
+    pub is_dir:     bool,
+
+    /// The size of the file in bytes. Always 0 for directories
+    pub size:       i64,
+
+    /// The modification time of the file, in seconds since the epoch. Always 0 for directories
+    pub mtime:      i64,
+
+    /// The MD5 checksum of the file's contents, if known
+    pub checksum:   Option<String>
+}
+
+/// Load the sync state of all entries under the current root folder, as synced by the profile of `env`
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn load_all(env: &Env) -> Result<HashMap<PathBuf, SyncState>> {
+    let conn = unwrap_db_err!(env.get_conn());
+    let mut stmt = unwrap_db_err!(conn.prepare("SELECT * FROM sync_state WHERE root_id = :root_id AND profile = :profile"));
+    let mut result = unwrap_db_err!(stmt.query(named_params! {
+        ":root_id": &env.root_folder,
+        ":profile": &env.profile
+    }));
+
+    let mut states = HashMap::new();
+    while let Ok(Some(row)) = result.next() {
+        let path = PathBuf::from(unwrap_db_err!(row.get::<&str, String>("path")));
+        let state = SyncState {
+            path:       path.clone(),
+            drive_id:   unwrap_db_err!(row.get::<&str, String>("drive_id")),
+            parent_id:  unwrap_db_err!(row.get::<&str, String>("parent_id")),
+            is_dir:     unwrap_db_err!(row.get::<&str, bool>("is_dir")),
+            size:       unwrap_db_err!(row.get::<&str, i64>("size")),
+            mtime:      unwrap_db_err!(row.get::<&str, i64>("mtime")),
+            checksum:   unwrap_db_err!(row.get::<&str, Option<String>>("checksum"))
+        };
+
+        states.insert(path, state);
+    }
+
+    Ok(states)
+}
+
+/// Save the sync state of an entry, replacing any previously stored state for the same path
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn save(env: &Env, state: &SyncState) -> Result<()> {
+    let conn = unwrap_db_err!(env.get_conn());
+    unwrap_db_err!(conn.execute("INSERT OR REPLACE INTO sync_state (path, root_id, drive_id, parent_id, is_dir, size, mtime, checksum, profile) VALUES (:path, :root_id, :drive_id, :parent_id, :is_dir, :size, :mtime, :checksum, :profile)", named_params! {
+        ":path":        state.path.to_str().unwrap(),
+        ":root_id":     &env.root_folder,
+        ":drive_id":    &state.drive_id,
+        ":parent_id":   &state.parent_id,
+        ":is_dir":      state.is_dir,
+        ":size":        state.size,
+        ":mtime":       state.mtime,
+        ":checksum":    &state.checksum,
+        ":profile":     &env.profile
+    }));
+
+    Ok(())
+}
+
+/// Remove the sync state of an entry, and of everything below it if it is a directory
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn remove(env: &Env, path: &Path) -> Result<()> {
+    let path = path.to_str().unwrap();
+    let conn = unwrap_db_err!(env.get_conn());
+    unwrap_db_err!(conn.execute("DELETE FROM sync_state WHERE root_id = :root_id AND profile = :profile AND (path = :path OR substr(path, 1, length(:prefix)) = :prefix)", named_params! {
+        ":root_id": &env.root_folder,
+        ":profile": &env.profile,
+        ":path":    path,
+        ":prefix":  &format!("{}{}", path, std::path::MAIN_SEPARATOR)
+    }));
+
+    Ok(())
+}
+
+/// Get the page token from which changes in Google Drive since the previous sync of the current root folder can be listed
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn load_page_token(env: &Env) -> Result<Option<String>> {
+    let conn = unwrap_db_err!(env.get_conn());
+    let mut stmt = unwrap_db_err!(conn.prepare("SELECT page_token FROM change_tokens WHERE root_id = :root_id AND profile = :profile"));
+    let mut result = unwrap_db_err!(stmt.query(named_params! {
+        ":root_id": &env.root_folder,
+        ":profile": &env.profile
+    }));
+
+    match result.next() {
+        Ok(Some(row)) => Ok(Some(unwrap_db_err!(row.get::<&str, String>("page_token")))),
+        _ => Ok(None)
+    }
+}
+
+/// Save the page token from which changes in Google Drive should be listed during the next sync of the current root folder
+///
+/// ## Errors
+/// - When a database operation fails
+pub fn save_page_token(env: &Env, page_token: &str) -> Result<()> {
+    let conn = unwrap_db_err!(env.get_conn());
+    unwrap_db_err!(conn.execute("INSERT OR REPLACE INTO change_tokens (root_id, page_token, profile) VALUES (:root_id, :page_token, :profile)", named_params! {
+        ":root_id":     &env.root_folder,
+        ":page_token":  page_token,
+        ":profile":     &env.profile
+    }));
+
+    Ok(())
+}
@@ -0,0 +1,454 @@
+//! Module related to two-way syncing, where changes made in Google Drive are pulled to the local files as well
+
+use super::{SyncContext, Child, Directory, BoxFuture, sync_child, remove_remote, get_size_and_modification_time, md5_checksum};
+use super::state::{self, SyncState};
+use super::exclusions::ExclusionStack;
+use crate::{Result, unwrap_io_err};
+use crate::error::ResultExt;
+use crate::api::drive::{self, FOLDER_MIME_TYPE};
+use crate::backend::Backend;
+use crate::env::Env;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::fs;
+
+/// Find out which folders in Google Drive changed since the previous sync, using the changes API.
+/// Returns the IDs of those folders, or `None` if this is unknown, and the page token to save once the sync succeeds,
+/// unless the backend can't list changes
+///
+/// # Errors
+/// - When a request to Google fails
+/// - When a database operation fails
+pub async fn changed_folders(env: &Env, backend: &dyn Backend, known: &HashMap<PathBuf, SyncState>) -> Result<(Option<HashSet<String>>, Option<String>)> {
+    if let Some(page_token) = state::load_page_token(env)? {
+        println!("Info: Listing changes in {} since the previous sync", backend.describe());
+        match backend.list_changes(&page_token).await {
+            Ok((changes, page_token)) => {
+                let changed_folders = folders_touched(&changes, known);
+                println!("Info: Found {} changes in {} folders.", changes.len(), changed_folders.len());
+                return Ok((Some(changed_folders), Some(page_token)));
+            },
+            // E.g. when the token expired. Listing every folder is always correct
+            Err(e) => println!("Warning: Failed to list changes in {}, checking every folder instead: {}", backend.describe(), e.report())
+        }
+    }
+
+    // Obtained before syncing, so changes made while syncing are listed during the next sync
+    let page_token = backend.start_page_token().await?;
+    Ok((None, page_token))
+}
+
+/// Get the IDs of the folders whose contents are affected by the changes: the current parents of every changed file,
+/// and the parent it had during the previous sync, which differs when the file was moved or removed
+fn folders_touched(changes: &[drive::Change], known: &HashMap<PathBuf, SyncState>) -> HashSet<String> {
+    let previous_parents = known.values()
+        .map(|state| (state.drive_id.as_str(), state.parent_id.as_str()))
+        .collect::<HashMap<_, _>>();
+
+    let mut folders = HashSet::new();
+    for change in changes {
+        // Removed files don't report their parents anymore, for those only the previous parent is known
+        if let (false, Some(file)) = (change.removed, &change.file) {
+            folders.extend(file.parents.iter().cloned());
+        }
+
+        if let Some(parent_id) = previous_parents.get(change.file_id.as_str()) {
+            folders.insert(parent_id.to_string());
+        }
+    }
+
+    folders
+}
+
+/// Get the contents of a folder in Google Drive from the stored state, if the folder didn't change since the previous sync.
+/// Returns `None` if the folder has to be listed, because it changed or because not all local entries have a stored state
+fn unchanged_listing(ctx: &SyncContext<'_>, dir: &Directory, folder_id: &str) -> Option<Vec<drive::File>> {
+    let changed_folders = ctx.changed_folders.as_ref()?;
+    if changed_folders.contains(folder_id) {
+        return None;
+    }
+
+    let all_known = dir.children.iter()
+        .all(|child| ctx.known(child.path(), folder_id, matches!(child, Child::Directory(_) | Child::Untraversed(_))).is_some());
+    if !all_known {
+        return None;
+    }
+
+    let listing = ctx.known.values()
+        .filter(|state| state.parent_id == folder_id)
+        .map(|state| drive::File {
+            id:             state.drive_id.clone(),
+            name:           state.path.file_name().unwrap().to_str().unwrap().to_string(),
+            mime_type:      if state.is_dir { FOLDER_MIME_TYPE.to_string() } else { "application/octet-stream".to_string() },
+            modified_time:  String::new(),
+            md5_checksum:   state.checksum.clone(),
+            size:           Some(state.size),
+            parents:        vec![folder_id.to_string()]
+        })
+        .collect();
+
+    Some(listing)
+}
+
+/// Sync the children of a directory in both directions. Subdirectories are synced using `sync_child`
+///
+/// # Errors
+/// - When a request to Google fails
+/// - When a database operation fails
+/// - When an IO operation on a local file fails
+///
+/// Folders which didn't change in Google Drive since the previous sync are not listed, the stored state is used instead
+pub async fn sync_directory(ctx: &mut SyncContext<'_>, dir: Directory, folder_id: &str, exclusions: &mut ExclusionStack) -> Result<()> {
+    let remote_children = match unchanged_listing(ctx, &dir, folder_id) {
+        Some(remote_children) => remote_children,
+        None => ctx.backend.list_folder(folder_id).await?
+    };
+    let mut remote_by_name = HashMap::new();
+    for remote in remote_children {
+        remote_by_name.entry(remote.name.clone()).or_insert(remote);
+    }
+
+    for child in dir.children {
+        let remote = remote_by_name.remove(child.name());
+        match child {
+            Child::Directory(subdir) => {
+                let remote_is_folder = remote.map(|remote| remote.mime_type == FOLDER_MIME_TYPE).unwrap_or(false);
+                if !remote_is_folder && ctx.known(&subdir.path, folder_id, true).is_some() {
+                    // The folder was removed from Drive since the previous sync
+                    if ctx.options.delete {
+                        remove_local(ctx, subdir.path, true)?;
+                        continue;
+                    }
+
+                    // A dry run doesn't touch the stored state, so the next run still knows the folder was removed from Drive
+                    if !ctx.options.dry_run {
+                        ctx.forget(&subdir.path)?;
+                    }
+                }
+
+                sync_child(ctx, Child::Directory(subdir), folder_id, exclusions).await?;
+            },
+            Child::File(path) => {
+                let result = sync_file(ctx, path.clone(), folder_id, remote).await;
+                ctx.check(path, result)?;
+            },
+            // Its failure was already recorded while traversing, and its counterpart in Drive is left alone
+            Child::Untraversed(_) => {}
+        }
+    }
+
+    // What's left only exists in Drive
+    for (name, remote) in remote_by_name {
+        let path = dir.path.join(&name);
+        let is_dir = remote.mime_type == FOLDER_MIME_TYPE;
+        let excluded = exclusions.is_excluded(&path, is_dir);
+
+        if ctx.options.delete && (excluded || ctx.known(&path, folder_id, is_dir).is_some()) {
+            // Removed locally since the previous sync, or ignored
+            let result = remove_remote(ctx, path.clone(), &remote.id).await;
+            ctx.check(path, result)?;
+        } else if !excluded {
+            let result = pull(ctx, &remote, path.clone(), folder_id, exclusions).await;
+            ctx.check(path, result)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Sync a single file in both directions. `remote` is the file with the same name in Google Drive, if there is one
+///
+/// # Errors
+/// - When a request to Google fails
+/// - When a database operation fails
+/// - When an IO operation on a local file fails
+async fn sync_file(ctx: &mut SyncContext<'_>, path: PathBuf, parent_id: &str, remote: Option<drive::File>) -> Result<()> {
+    let file_name = path.file_name().unwrap().to_str().unwrap().to_string();
+    let state = ctx.known(&path, parent_id, false).cloned();
+
+    let remote = match remote.filter(|remote| remote.mime_type != FOLDER_MIME_TYPE) {
+        Some(remote) => remote,
+        None if state.is_some() && ctx.options.delete => {
+            // The file was removed from Drive since the previous sync
+            return remove_local(ctx, path, false);
+        },
+        None => {
+            if state.is_some() && !ctx.options.dry_run {
+                ctx.forget(&path)?;
+            }
+
+            return upload_new(ctx, path, parent_id).await;
+        }
+    };
+    let remote_checksum = ctx.backend.checksum(&remote).await?;
+
+    let (size, mtime) = get_size_and_modification_time(&path)?;
+    let unchanged_locally = state.as_ref().map(|state| state.size == size && state.mtime == mtime).unwrap_or(false);
+    let checksum = match state.as_ref().and_then(|state| state.checksum.clone()) {
+        Some(checksum) if unchanged_locally => checksum,
+        _ => md5_checksum(&path)?
+    };
+
+    let local_changed = match &state {
+        Some(state) => state.size != size || state.checksum.as_ref() != Some(&checksum),
+        None => true
+    };
+
+    // Files without a checksum, e.g. Google Docs, can't be pulled, so they are never considered to be changed
+    let remote_changed = match (&state, &remote_checksum) {
+        (_, None) => false,
+        (Some(state), Some(remote_checksum)) => state.drive_id != remote.id || state.checksum.as_ref() != Some(remote_checksum),
+        (None, Some(_)) => true
+    };
+
+    let in_sync = (!local_changed && !remote_changed) || remote_checksum.as_ref() == Some(&checksum);
+    if in_sync {
+        println!("Info: File '{}' is up-to-date.", &file_name);
+        if !unchanged_locally && !ctx.options.dry_run {
+            save_file_state(ctx, &path, &remote.id, parent_id, Some(checksum))?;
+        }
+
+        return Ok(());
+    }
+
+    if !remote_changed {
+        if ctx.options.dry_run {
+            ctx.report.updated.push(path);
+            return Ok(());
+        }
+
+        println!("Info: Updating file '{}'", &file_name);
+        ctx.backend.update_file(&path, &remote.id).await.with_file_id(&remote.id)?;
+        save_file_state(ctx, &path, &remote.id, parent_id, Some(checksum))?;
+        ctx.report.updated.push(path);
+        return Ok(());
+    }
+
+    if !local_changed {
+        if ctx.options.dry_run {
+            ctx.report.downloaded.push(path);
+            return Ok(());
+        }
+
+        println!("Info: Downloading file '{}'", &file_name);
+        ctx.backend.download_file(&remote.id, &path).await.with_file_id(&remote.id)?;
+        save_file_state(ctx, &path, &remote.id, parent_id, remote_checksum)?;
+        ctx.report.downloaded.push(path);
+        return Ok(());
+    }
+
+    // Both sides changed. Keep both copies: the copy from Drive is saved next to the local file,
+    // after which the local version is uploaded and the copy from Drive is uploaded as a new file
+    let timestamp = chrono::Local::now().format("%Y%m%d-%H%M%S").to_string();
+    let conflict_path = conflict_path(&path, &timestamp);
+    if ctx.options.dry_run {
+        ctx.report.conflicts.push((path, conflict_path));
+        return Ok(());
+    }
+
+    println!("Warning: File '{}' was changed both locally and in {}. Saving the version from {} as '{}'", &file_name, ctx.backend.describe(), ctx.backend.describe(), conflict_path.file_name().unwrap().to_str().unwrap());
+    ctx.backend.download_file(&remote.id, &conflict_path).await.with_path(&conflict_path).with_file_id(&remote.id)?;
+    let conflict_id = ctx.backend.upload_file(&conflict_path, parent_id).await?;
+    save_file_state(ctx, &conflict_path, &conflict_id, parent_id, remote_checksum)?;
+
+    ctx.backend.update_file(&path, &remote.id).await.with_file_id(&remote.id)?;
+    save_file_state(ctx, &path, &remote.id, parent_id, Some(checksum))?;
+    ctx.report.conflicts.push((path, conflict_path));
+
+    Ok(())
+}
+
+/// Upload a file which doesn't exist in Google Drive
+///
+/// # Errors
+/// - When a request to Google fails
+/// - When a database operation fails
+/// - When an IO operation on the local file fails
+async fn upload_new(ctx: &mut SyncContext<'_>, path: PathBuf, parent_id: &str) -> Result<()> {
+    if ctx.options.dry_run {
+        ctx.report.uploaded.push(path);
+        return Ok(());
+    }
+
+    println!("Info: Uploading file '{}'", path.file_name().unwrap().to_str().unwrap());
+    let id = ctx.backend.upload_file(&path, parent_id).await?;
+    let checksum = md5_checksum(&path)?;
+    save_file_state(ctx, &path, &id, parent_id, Some(checksum))?;
+    ctx.report.uploaded.push(path);
+
+    Ok(())
+}
+
+/// Download a file or folder which only exists in Google Drive. Folders are downloaded recursively,
+/// skipping everything that is excluded
+///
+/// # Errors
+/// - When a request to Google fails
+/// - When a database operation fails
+/// - When an IO operation on a local file or directory fails
+fn pull<'a>(ctx: &'a mut SyncContext<'_>, remote: &'a drive::File, path: PathBuf, parent_id: &'a str, exclusions: &'a ExclusionStack) -> BoxFuture<'a, Result<()>> {
+    Box::pin(async move {
+        if remote.mime_type == FOLDER_MIME_TYPE {
+            if ctx.options.dry_run {
+                ctx.report.downloaded.push(path);
+                return Ok(());
+            }
+
+            println!("Info: Creating local directory '{}'", path.to_str().unwrap());
+            unwrap_io_err!(fs::create_dir_all(&path));
+            state::save(ctx.env, &SyncState {
+                path:       path.clone(),
+                drive_id:   remote.id.clone(),
+                parent_id:  parent_id.to_string(),
+                is_dir:     true,
+                size:       0,
+                mtime:      0,
+                checksum:   None
+            })?;
+
+            let remote_children = ctx.backend.list_folder(&remote.id).await?;
+            ctx.report.downloaded.push(path.clone());
+
+            for child in remote_children {
+                let child_path = path.join(&child.name);
+                if !exclusions.is_excluded(&child_path, child.mime_type == FOLDER_MIME_TYPE) {
+                    let result = pull(ctx, &child, child_path.clone(), &remote.id, exclusions).await;
+                    ctx.check(child_path, result)?;
+                }
+            }
+
+            return Ok(());
+        }
+
+        // Google Docs, Sheets etc. have no binary content which could be downloaded
+        let checksum = ctx.backend.checksum(remote).await?;
+        if checksum.is_none() {
+            println!("Info: Skipping '{}', files of type '{}' can't be downloaded", path.to_str().unwrap(), &remote.mime_type);
+            return Ok(());
+        }
+
+        if ctx.options.dry_run {
+            ctx.report.downloaded.push(path);
+            return Ok(());
+        }
+
+        println!("Info: Downloading file '{}'", path.to_str().unwrap());
+        ctx.backend.download_file(&remote.id, &path).await.with_file_id(&remote.id)?;
+        save_file_state(ctx, &path, &remote.id, parent_id, checksum)?;
+        ctx.report.downloaded.push(path);
+
+        Ok(())
+    })
+}
+
+/// Remove a local file or directory which was removed from Google Drive
+///
+/// # Errors
+/// - When removing the file or directory fails
+/// - When a database operation fails
+fn remove_local(ctx: &mut SyncContext<'_>, path: PathBuf, is_dir: bool) -> Result<()> {
+    if ctx.options.dry_run {
+        ctx.report.removed.push(path);
+        return Ok(());
+    }
+
+    println!("Info: Removing '{}', it was removed from {}", path.to_str().unwrap(), ctx.backend.describe());
+    if is_dir {
+        unwrap_io_err!(fs::remove_dir_all(&path));
+    } else {
+        unwrap_io_err!(fs::remove_file(&path));
+    }
+
+    ctx.forget(&path)?;
+    ctx.report.removed.push(path);
+
+    Ok(())
+}
+
+/// Save the state of a file as it currently is on disk
+///
+/// # Errors
+/// - When fetching the file's metadata fails
+/// - When a database operation fails
+fn save_file_state(ctx: &SyncContext<'_>, path: &Path, drive_id: &str, parent_id: &str, checksum: Option<String>) -> Result<()> {
+    let (size, mtime) = get_size_and_modification_time(path)?;
+    state::save(ctx.env, &SyncState {
+        path:       path.to_path_buf(),
+        drive_id:   drive_id.to_string(),
+        parent_id:  parent_id.to_string(),
+        is_dir:     false,
+        size,
+        mtime,
+        checksum
+    })
+}
+
+/// Get the path to save the copy from Google Drive of a conflicting file to, e.g. `notes.conflict-20210801-120000.txt` for `notes.txt`
+fn conflict_path(path: &Path, timestamp: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap().to_str().unwrap();
+    let name = match path.extension() {
+        Some(extension) => format!("{}.conflict-{}.{}", stem, timestamp, extension.to_str().unwrap()),
+        None => format!("{}.conflict-{}", stem, timestamp)
+    };
+
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{conflict_path, folders_touched};
+    use super::super::state::SyncState;
+    use crate::api::drive::{Change, File};
+    use std::collections::HashMap;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn conflict_path_keeps_extension() {
+        assert_eq!(PathBuf::from("/repo/notes.conflict-20210801-120000.txt"), conflict_path(Path::new("/repo/notes.txt"), "20210801-120000"));
+    }
+
+    #[test]
+    fn conflict_path_without_extension() {
+        assert_eq!(PathBuf::from("/repo/Makefile.conflict-20210801-120000"), conflict_path(Path::new("/repo/Makefile"), "20210801-120000"));
+    }
+
+    #[test]
+    fn folders_touched_includes_previous_and_current_parents() {
+        let mut known = HashMap::new();
+        known.insert(PathBuf::from("/repo/moved.txt"), SyncState {
+            path:       PathBuf::from("/repo/moved.txt"),
+            drive_id:   "moved".to_string(),
+            parent_id:  "old-parent".to_string(),
+            is_dir:     false,
+            size:       0,
+            mtime:      0,
+            checksum:   None
+        });
+
+        let changes = vec![
+            Change {
+                file_id:    "moved".to_string(),
+                removed:    false,
+                file:       Some(File {
+                    id:             "moved".to_string(),
+                    name:           "moved.txt".to_string(),
+                    mime_type:      "text/plain".to_string(),
+                    modified_time:  String::new(),
+                    md5_checksum:   None,
+                    size:           None,
+                    parents:        vec!["new-parent".to_string()]
+                })
+            },
+            Change {
+                file_id:    "unknown".to_string(),
+                removed:    true,
+                file:       None
+            }
+        ];
+
+        let folders = folders_touched(&changes, &known);
+        assert_eq!(2, folders.len());
+        assert!(folders.contains("old-parent"));
+        assert!(folders.contains("new-parent"));
+    }
+}
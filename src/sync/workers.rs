@@ -0,0 +1,75 @@
+//! Module for syncing files concurrently, on a bounded number of workers
+
+use super::{SyncOptions, SyncReport, FileOutcome, sync_file};
+use super::state::SyncState;
+use crate::backend::Backend;
+use crate::env::Env;
+use crate::{Result, ErrorKind, unwrap_other_err, new_err};
+use crate::error::ResultExt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinHandle;
+
+/// Struct describing a pool of workers files are synced on
+pub struct Workers {
+    /// Limits the number of files being synced at the same time
+    semaphore:  Arc<Semaphore>,
+
+    /// The files which were handed to a worker, by local path
+    tasks:      Vec<(PathBuf, JoinHandle<Result<FileOutcome>>)>
+}
+
+impl Workers {
+    /// Create a pool which syncs at most `jobs` files at the same time
+    pub fn new(jobs: usize) -> Self {
+        Self {
+            semaphore:  Arc::new(Semaphore::new(jobs)),
+            tasks:      Vec::new()
+        }
+    }
+
+    /// Sync a file on a worker. Waits until a worker is available, so the caller doesn't run ahead of the workers
+    ///
+    /// # Errors
+    /// - When waiting for a worker fails
+    pub async fn spawn(&mut self, env: &Env, backend: &Arc<dyn Backend>, options: &SyncOptions, path: PathBuf, parent_id: &str, state: Option<SyncState>) -> Result<()> {
+        let permit = unwrap_other_err!(self.semaphore.clone().acquire_owned().await);
+        let (env, backend, options, parent_id) = (env.clone(), backend.clone(), options.clone(), parent_id.to_string());
+
+        self.tasks.push((path.clone(), tokio::spawn(async move {
+            let _permit = permit;
+            sync_file(&env, backend.as_ref(), &options, path, &parent_id, state).await
+        })));
+
+        Ok(())
+    }
+
+    /// Wait for all files to be synced, and record what happened to them in `report`.
+    /// When continuing on errors, the files which failed to sync are recorded in `report` as well
+    ///
+    /// # Errors
+    /// - The first error which occurred while syncing a file, unless continuing on errors. All other files are synced regardless
+    pub async fn join(self, report: &mut SyncReport, continue_on_error: bool) -> Result<()> {
+        let mut first_error = None;
+        for (path, task) in self.tasks {
+            let result = match task.await {
+                Ok(result) => result,
+                Err(e) => Err(new_err!(ErrorKind::Other(format!("A worker failed: {}", e))))
+            };
+
+            match result.with_path(&path) {
+                Ok(outcome) => report.record(outcome),
+                Err(e) if continue_on_error => report.fail(path, e),
+                Err(e) if first_error.is_none() => first_error = Some(e),
+                // Only the first error is returned, so the others are printed
+                Err(e) => eprintln!("Error: Failed to sync a file: {}", e.report())
+            }
+        }
+
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(())
+        }
+    }
+}
@@ -0,0 +1,663 @@
+//! An in-process fake of the Google Drive v3 and OAuth2 APIs, so `gsync` can be tested end to end without network access.
+//! Only the parts of the APIs GSync uses are implemented
+
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use actix_web::http::{header, Method, StatusCode};
+use md5::Digest;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{mpsc, Arc, Mutex};
+
+/// The access token the fake OAuth2 API hands out, and the fake Drive API accepts
+pub const ACCESS_TOKEN: &str = "fake-access-token";
+
+/// The authorization code the fake OAuth2 API exchanges for tokens
+pub const AUTHORIZATION_CODE: &str = "4/fake-authorization-code";
+
+/// The code the user enters in the device flow
+pub const USER_CODE: &str = "FAKE-CODE";
+
+/// The ID of a client of type 'Desktop app', which Google doesn't accept for the device flow
+pub const DESKTOP_CLIENT_ID: &str = "desktop-client-id";
+
+/// The number of times the device flow is polled before the fake user entered the code
+const DEVICE_POLLS_PENDING: usize = 2;
+
+/// The MIME type Google Drive uses for folders
+pub const FOLDER_MIME_TYPE: &str = "application/vnd.google-apps.folder";
+
+/// Struct describing a file or folder stored in the fake Drive
+#[derive(Clone, Debug)]
+pub struct FakeFile {
+    /// The ID of the file
+    pub id:             String,
+
+    /// The name of the file
+    pub name:           String,
+
+    /// The MIME type of the file
+    pub mime_type:      String,
+
+    /// The IDs of the parent folders of the file
+    pub parents:        Vec<String>,
+
+    /// Whether the file was moved to the trash
+    pub trashed:        bool,
+
+    /// The contents of the file. Empty for folders
+    pub content:        Vec<u8>,
+
+    /// The time the file was last modified, in RFC 3339 format
+    pub modified_time:  String
+}
+
+/// Struct describing everything stored in the fake Drive
+#[derive(Default)]
+struct State {
+    /// The files and folders, by ID
+    files:          HashMap<String, FakeFile>,
+
+    /// The shared drives, as (ID, name)
+    drives:         Vec<(String, String)>,
+
+    /// The number of IDs handed out so far
+    ids:            usize,
+
+    /// The number of requests to the token endpoint
+    token_requests: usize,
+
+    /// The number of times the device flow was polled
+    device_polls:   usize,
+
+    /// The tokens which were revoked
+    revoked:        Vec<String>,
+
+    /// The sessions of resumable uploads, by ID
+    sessions:       HashMap<String, Session>,
+
+    /// The IDs of the files which changed, in order. A page token of the changes API is an index in this list
+    changes:        Vec<String>,
+
+    /// The number of chunks of resumable uploads which still have to fail with a server error, after half of the chunk was received
+    failing_chunks: usize
+}
+
+/// Struct describing the session of a resumable upload
+struct Session {
+    /// The metadata the session was started with
+    metadata:   Value,
+
+    /// The ID of the file which is updated, or `None` if the upload creates a file
+    file_id:    Option<String>,
+
+    /// The size of the file, as announced when starting the session
+    size:       usize,
+
+    /// The bytes received so far
+    content:    Vec<u8>,
+
+    /// The uploaded file, once the upload is complete
+    complete:   Option<Value>
+}
+
+/// A running fake Drive server. The server runs until the test process exits
+pub struct FakeDrive {
+    /// The base URL of the server, to be used for both the Google APIs and the OAuth2 API
+    pub url:    String,
+
+    /// Everything stored in the fake Drive, shared with the server
+    state:      Arc<Mutex<State>>
+}
+
+impl FakeDrive {
+    /// Start a fake Drive server on a free port
+    pub fn start() -> Self {
+        let state = Arc::new(Mutex::new(State::default()));
+        let (tx, rx) = mpsc::channel();
+
+        let server_state = state.clone();
+        std::thread::spawn(move || {
+            let sys = actix_web::rt::System::new("fake-drive");
+            let data = web::Data::from(server_state);
+            let server = HttpServer::new(move || App::new()
+                    .app_data(data.clone())
+                    // Chunks of resumable uploads are larger than the default limit
+                    .app_data(web::PayloadConfig::new(64 * 1024 * 1024))
+                    .default_service(web::route().to(handle)))
+                .workers(1)
+                .bind("127.0.0.1:0")
+                .expect("Failed to bind the fake Drive server");
+
+            tx.send(server.addrs()[0]).unwrap();
+            server.run();
+            sys.run().unwrap();
+        });
+
+        let addr = rx.recv().expect("The fake Drive server failed to start");
+        Self {
+            url:    format!("http://{}", addr),
+            state
+        }
+    }
+
+    /// Add a shared drive
+    pub fn add_shared_drive(&self, id: &str, name: &str) {
+        self.state.lock().unwrap().drives.push((id.to_string(), name.to_string()));
+    }
+
+    /// Find a file or folder by its path from the root of 'My Drive', e.g. `GSync/files/a.txt`. Trashed files are found as well
+    pub fn find(&self, path: &str) -> Option<FakeFile> {
+        self.find_in("root", path)
+    }
+
+    /// Find a file or folder by its path from the folder or shared drive with ID `root`. Trashed files are found as well
+    pub fn find_in(&self, root: &str, path: &str) -> Option<FakeFile> {
+        lookup(&self.state.lock().unwrap(), root, path)
+    }
+
+    /// Change the contents of a file by its path from the root of 'My Drive', or create it in its parent folder, as another client of Drive would
+    pub fn write(&self, path: &str, content: &str) {
+        let mut state = self.state.lock().unwrap();
+        let id = match lookup(&state, "root", path).filter(|file| !file.trashed) {
+            Some(file) => file.id,
+            None => {
+                let (parent, name) = path.rsplit_once('/').unwrap();
+                let parent = lookup(&state, "root", parent).unwrap_or_else(|| panic!("'{}' is not in Drive", parent));
+                let id = new_id(&mut state);
+                create_file(&mut state, &json!({ "id": id, "name": name, "parents": [parent.id], "mimeType": "text/plain" }), Vec::new());
+                id
+            }
+        };
+
+        let file = state.files.get_mut(&id).unwrap();
+        file.content = content.as_bytes().to_vec();
+        file.modified_time = chrono::Utc::now().to_rfc3339();
+        state.changes.push(id);
+    }
+
+    /// Move a file or folder to the trash by its path from the root of 'My Drive', as another client of Drive would
+    pub fn trash(&self, path: &str) {
+        let mut state = self.state.lock().unwrap();
+        let id = lookup(&state, "root", path).unwrap_or_else(|| panic!("'{}' is not in Drive", path)).id;
+        state.files.get_mut(&id).unwrap().trashed = true;
+        state.changes.push(id);
+    }
+
+    /// Let the next `count` chunks of resumable uploads fail with a server error, after half of the chunk was received
+    pub fn fail_chunks(&self, count: usize) {
+        self.state.lock().unwrap().failing_chunks = count;
+    }
+
+    /// The tokens which were revoked
+    pub fn revoked_tokens(&self) -> Vec<String> {
+        self.state.lock().unwrap().revoked.clone()
+    }
+
+    /// The number of times an access token was requested
+    pub fn token_requests(&self) -> usize {
+        self.state.lock().unwrap().token_requests
+    }
+}
+
+/// Handle any request to the fake server
+async fn handle(req: HttpRequest, body: web::Bytes, state: web::Data<Mutex<State>>) -> HttpResponse {
+    let mut state = state.lock().unwrap();
+    let query = web::Query::<HashMap<String, String>>::from_query(req.query_string())
+        .map(web::Query::into_inner)
+        .unwrap_or_default();
+
+    if req.path() == "/token" && req.method() == Method::POST {
+        state.token_requests += 1;
+        let request: Value = serde_json::from_slice(&body).unwrap_or_default();
+        return match request["grant_type"].as_str() {
+            Some("urn:ietf:params:oauth:grant-type:device_code") => {
+                state.device_polls += 1;
+                if state.device_polls <= DEVICE_POLLS_PENDING {
+                    HttpResponse::build(StatusCode::PRECONDITION_REQUIRED).json(json!({ "error": "authorization_pending", "error_description": "Precondition Required" }))
+                } else {
+                    HttpResponse::Ok().json(json!({ "access_token": ACCESS_TOKEN, "expires_in": 3600, "refresh_token": "fake-refresh-token" }))
+                }
+            },
+            Some("refresh_token") => HttpResponse::Ok().json(json!({ "access_token": ACCESS_TOKEN, "expires_in": 3600 })),
+            // The signature isn't checked, only that the assertion is a JWT for this token endpoint
+            Some("urn:ietf:params:oauth:grant-type:jwt-bearer") => match request["assertion"].as_str().and_then(jwt_claims) {
+                Some(claims) if claims["aud"].as_str().is_some_and(|aud| aud.ends_with("/token")) && claims["iss"].is_string() => {
+                    HttpResponse::Ok().json(json!({ "access_token": ACCESS_TOKEN, "expires_in": 3600 }))
+                },
+                _ => error(StatusCode::BAD_REQUEST, "invalid_grant", "Invalid JWT")
+            },
+            Some("authorization_code") if request["code"] == AUTHORIZATION_CODE => HttpResponse::Ok().json(json!({
+                "access_token": ACCESS_TOKEN,
+                "expires_in": 3600,
+                "refresh_token": "fake-refresh-token"
+            })),
+            _ => error(StatusCode::BAD_REQUEST, "invalid_grant", "Bad Request")
+        };
+    }
+
+    if req.path() == "/revoke" && req.method() == Method::POST {
+        let form = web::Query::<HashMap<String, String>>::from_query(std::str::from_utf8(&body).unwrap_or_default());
+        return match form.ok().and_then(|form| form.get("token").cloned()) {
+            Some(token) => {
+                state.revoked.push(token);
+                HttpResponse::Ok().json(json!({}))
+            },
+            None => HttpResponse::BadRequest().json(json!({ "error": "invalid_request", "error_description": "Missing required parameter: token" }))
+        };
+    }
+
+    if req.path() == "/device/code" && req.method() == Method::POST {
+        let request: Value = serde_json::from_slice(&body).unwrap_or_default();
+        if request["client_id"] == DESKTOP_CLIENT_ID {
+            return HttpResponse::Unauthorized().json(json!({ "error": "invalid_client", "error_description": "Invalid client type." }));
+        }
+
+        return HttpResponse::Ok().json(json!({
+            "device_code": "fake-device-code",
+            "user_code": USER_CODE,
+            "verification_url": format!("http://{}/device", req.connection_info().host()),
+            "expires_in": 1800,
+            "interval": 0
+        }));
+    }
+
+    let authorization = req.headers().get(header::AUTHORIZATION).and_then(|value| value.to_str().ok());
+    if authorization != Some(format!("Bearer {}", ACCESS_TOKEN).as_str()) {
+        return error(StatusCode::UNAUTHORIZED, "authError", "Invalid Credentials");
+    }
+
+    let segments: Vec<&str> = req.path().trim_matches('/').split('/').collect();
+    match (req.method().clone(), segments.as_slice()) {
+        (Method::GET, ["drive", "v3", "files", "generateIds"]) => {
+            let count = query.get("count").and_then(|count| count.parse().ok()).unwrap_or(10);
+            let ids: Vec<String> = (0..count).map(|_| new_id(&mut state)).collect();
+            HttpResponse::Ok().json(json!({ "ids": ids }))
+        },
+        (Method::GET, ["drive", "v3", "files"]) => list_files(&state, &query),
+        (Method::POST, ["upload", "drive", "v3", "files"]) if query.get("uploadType").map(String::as_str) == Some("resumable") => {
+            start_session(&mut state, &req, &body, None)
+        },
+        (Method::PATCH, ["upload", "drive", "v3", "files", id]) if query.get("uploadType").map(String::as_str) == Some("resumable") => {
+            match state.files.contains_key(*id) {
+                true => start_session(&mut state, &req, &body, Some(id.to_string())),
+                false => not_found(id)
+            }
+        },
+        (Method::PUT, ["upload", "sessions", id]) => upload_chunk(&mut state, &req, &body, id),
+        (Method::POST, ["drive", "v3", "files"]) => {
+            let metadata: Value = match serde_json::from_slice(&body) {
+                Ok(metadata) => metadata,
+                Err(_) => return error(StatusCode::BAD_REQUEST, "parseError", "Invalid metadata")
+            };
+
+            create_file(&mut state, &metadata, Vec::new())
+        },
+        (Method::POST, ["upload", "drive", "v3", "files"]) => match multipart(&req, &body) {
+            Some((metadata, content)) => create_file(&mut state, &metadata, content),
+            None => error(StatusCode::BAD_REQUEST, "badContent", "Unsupported upload")
+        },
+        (Method::PATCH, ["upload", "drive", "v3", "files", id]) => {
+            let (metadata, content) = match multipart(&req, &body) {
+                Some(parts) => parts,
+                None => return error(StatusCode::BAD_REQUEST, "badContent", "Unsupported upload")
+            };
+
+            match state.files.get_mut(*id) {
+                Some(file) => {
+                    if let Some(mime_type) = metadata["mimeType"].as_str() {
+                        file.mime_type = mime_type.to_string();
+                    }
+
+                    file.content = content;
+                    file.modified_time = chrono::Utc::now().to_rfc3339();
+                    let response = to_json(file);
+                    state.changes.push(id.to_string());
+                    HttpResponse::Ok().json(response)
+                },
+                None => not_found(id)
+            }
+        },
+        (Method::PATCH, ["drive", "v3", "files", id]) => {
+            let metadata: Value = serde_json::from_slice(&body).unwrap_or_default();
+            match state.files.get_mut(*id) {
+                Some(file) => {
+                    if let Some(trashed) = metadata["trashed"].as_bool() {
+                        file.trashed = trashed;
+                    }
+
+                    let response = to_json(file);
+                    state.changes.push(id.to_string());
+                    HttpResponse::Ok().json(response)
+                },
+                None => not_found(id)
+            }
+        },
+        (Method::GET, ["drive", "v3", "files", id]) => match state.files.get(*id) {
+            Some(file) if query.get("alt").map(String::as_str) == Some("media") => HttpResponse::Ok().body(file.content.clone()),
+            Some(file) => HttpResponse::Ok().json(to_json(file)),
+            None => not_found(id)
+        },
+        (Method::DELETE, ["drive", "v3", "files", id]) => match state.files.remove(*id) {
+            // Like Google, respond without a body
+            Some(_) => {
+                state.changes.push(id.to_string());
+                HttpResponse::NoContent().finish()
+            },
+            None => not_found(id)
+        },
+        (Method::GET, ["drive", "v3", "changes", "startPageToken"]) => HttpResponse::Ok().json(json!({ "startPageToken": state.changes.len().to_string() })),
+        (Method::GET, ["drive", "v3", "changes"]) => list_changes(&state, &query),
+        (Method::GET, ["drive", "v3", "about"]) => HttpResponse::Ok().json(json!({
+            "user": { "displayName": "Fake User", "emailAddress": "user@example.com" }
+        })),
+        (Method::GET, ["drive", "v3", "drives"]) => {
+            let drives: Vec<Value> = state.drives.iter().map(|(id, name)| json!({ "id": id, "name": name })).collect();
+            HttpResponse::Ok().json(json!({ "drives": drives }))
+        },
+        _ => error(StatusCode::NOT_FOUND, "notFound", &format!("The fake Drive doesn't implement {} {}", req.method(), req.path()))
+    }
+}
+
+/// List the files matching the query, one page at a time
+fn list_files(state: &State, query: &HashMap<String, String>) -> HttpResponse {
+    let q = query.get("q").map(String::as_str).unwrap_or_default();
+    let mut files = Vec::new();
+    for file in state.files.values() {
+        match matches(file, q) {
+            Some(true) => files.push(file),
+            Some(false) => {},
+            None => return error(StatusCode::BAD_REQUEST, "invalid", &format!("The fake Drive doesn't support the query '{}'", q))
+        }
+    }
+
+    files.sort_by(|a, b| a.id.cmp(&b.id));
+    let page_size = query.get("pageSize").and_then(|page_size| page_size.parse().ok()).unwrap_or(100);
+    let offset: usize = query.get("pageToken").and_then(|page_token| page_token.parse().ok()).unwrap_or(0);
+
+    let page: Vec<Value> = files.iter().skip(offset).take(page_size).map(|file| to_json(file)).collect();
+    let mut response = json!({ "files": page });
+    if offset + page_size < files.len() {
+        response["nextPageToken"] = json!((offset + page_size).to_string());
+    }
+
+    HttpResponse::Ok().json(response)
+}
+
+/// List the changes since the page token, one page at a time. The page token is the index of the first change
+fn list_changes(state: &State, query: &HashMap<String, String>) -> HttpResponse {
+    let offset: usize = match query.get("pageToken").and_then(|page_token| page_token.parse().ok()) {
+        Some(offset) if offset <= state.changes.len() => offset,
+        _ => return error(StatusCode::BAD_REQUEST, "invalid", "Invalid page token")
+    };
+
+    let page_size = query.get("pageSize").and_then(|page_size| page_size.parse().ok()).unwrap_or(100);
+    let end = (offset + page_size).min(state.changes.len());
+    let changes: Vec<Value> = state.changes[offset..end].iter()
+        .map(|id| match state.files.get(id) {
+            Some(file) => json!({ "fileId": id, "removed": false, "file": to_json(file) }),
+            None => json!({ "fileId": id, "removed": true })
+        })
+        .collect();
+
+    let mut response = json!({ "changes": changes });
+    if end < state.changes.len() {
+        response["nextPageToken"] = json!(end.to_string());
+    } else {
+        response["newStartPageToken"] = json!(end.to_string());
+    }
+
+    HttpResponse::Ok().json(response)
+}
+
+/// Check whether a file matches a search query. Returns `None` if the query uses a term the fake doesn't support
+fn matches(file: &FakeFile, q: &str) -> Option<bool> {
+    if q.is_empty() {
+        return Some(true);
+    }
+
+    let mut matched = true;
+    for term in q.split(" and ").map(str::trim) {
+        matched &= if let Some(parent) = term.strip_suffix(" in parents") {
+            file.parents.contains(&unquote(parent)?)
+        } else if let Some(name) = term.strip_prefix("name = ") {
+            file.name == unquote(name)?
+        } else if let Some(mime_type) = term.strip_prefix("mimeType = ") {
+            file.mime_type == unquote(mime_type)?
+        } else if let Some(mime_type) = term.strip_prefix("mimeType != ") {
+            file.mime_type != unquote(mime_type)?
+        } else if let Some(trashed) = term.strip_prefix("trashed = ") {
+            file.trashed == (trashed == "true")
+        } else {
+            return None;
+        };
+    }
+
+    Some(matched)
+}
+
+/// Remove the quotes around a string in a search query, and undo its escaping. Returns `None` for an invalid string, e.g. one with an unescaped quote
+fn unquote(value: &str) -> Option<String> {
+    let value = value.trim().strip_prefix('\'')?.strip_suffix('\'')?;
+
+    let mut unquoted = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unquoted.push(chars.next()?),
+            '\'' => return None,
+            c => unquoted.push(c)
+        }
+    }
+
+    Some(unquoted)
+}
+
+/// Create a file from its metadata, using the ID in the metadata if there is one
+fn create_file(state: &mut State, metadata: &Value, content: Vec<u8>) -> HttpResponse {
+    let id = match metadata["id"].as_str() {
+        Some(id) => id.to_string(),
+        None => new_id(state)
+    };
+
+    let file = FakeFile {
+        id:             id.clone(),
+        name:           metadata["name"].as_str().unwrap_or_default().to_string(),
+        mime_type:      metadata["mimeType"].as_str().unwrap_or("application/octet-stream").to_string(),
+        parents:        metadata["parents"].as_array()
+            .map(|parents| parents.iter().filter_map(Value::as_str).map(str::to_string).collect())
+            .unwrap_or_else(|| vec!["root".to_string()]),
+        trashed:        false,
+        content,
+        modified_time:  chrono::Utc::now().to_rfc3339()
+    };
+
+    let response = to_json(&file);
+    state.changes.push(id.clone());
+    state.files.insert(id, file);
+    HttpResponse::Ok().json(response)
+}
+
+/// Start a resumable upload which creates a file, or updates the file with ID `file_id`. The session URI is returned in the `Location` header
+fn start_session(state: &mut State, req: &HttpRequest, body: &[u8], file_id: Option<String>) -> HttpResponse {
+    let mut metadata: Value = serde_json::from_slice(body).unwrap_or_default();
+    if file_id.is_none() && metadata["id"].is_null() {
+        metadata["id"] = json!(new_id(state));
+    }
+
+    let size = req.headers().get("X-Upload-Content-Length").and_then(|size| size.to_str().ok()).and_then(|size| size.parse().ok());
+    let size = match size {
+        Some(size) => size,
+        None => return error(StatusCode::BAD_REQUEST, "badContent", "Missing X-Upload-Content-Length")
+    };
+
+    let id = format!("session-{}", state.sessions.len() + 1);
+    state.sessions.insert(id.clone(), Session { metadata, file_id, size, content: Vec::new(), complete: None });
+    HttpResponse::Ok()
+        .header(header::LOCATION, format!("http://{}/upload/sessions/{}", req.connection_info().host(), id))
+        .finish()
+}
+
+/// Receive a chunk of a resumable upload, or report how much was received when the `Content-Range` is `bytes */<SIZE>`.
+/// Like Google, a chunk has to start where the received bytes end
+fn upload_chunk(state: &mut State, req: &HttpRequest, body: &[u8], id: &str) -> HttpResponse {
+    let range = req.headers().get(header::CONTENT_RANGE).and_then(|range| range.to_str().ok()).unwrap_or_default().to_string();
+    let failing = state.failing_chunks > 0;
+    let session = match state.sessions.get_mut(id) {
+        Some(session) => session,
+        None => return error(StatusCode::NOT_FOUND, "notFound", "Upload session not found")
+    };
+
+    let range = range.strip_prefix("bytes ").and_then(|range| range.split('/').next()).unwrap_or_default();
+    if range != "*" {
+        let start = range.split('-').next().and_then(|start| start.parse::<usize>().ok());
+        if session.complete.is_some() || start != Some(session.content.len()) {
+            return error(StatusCode::BAD_REQUEST, "badContent", &format!("The chunk doesn't start at the {} received bytes", session.content.len()));
+        }
+
+        if failing {
+            session.content.extend_from_slice(&body[..body.len() / 2]);
+            state.failing_chunks -= 1;
+            return error(StatusCode::SERVICE_UNAVAILABLE, "backendError", "Backend Error");
+        }
+
+        session.content.extend_from_slice(body);
+    }
+
+    if let Some(file) = &session.complete {
+        return HttpResponse::Ok().json(file);
+    }
+
+    if session.content.len() < session.size {
+        let mut response = HttpResponse::build(StatusCode::PERMANENT_REDIRECT);
+        if !session.content.is_empty() {
+            response.header(header::RANGE, format!("bytes=0-{}", session.content.len() - 1));
+        }
+
+        return response.finish();
+    }
+
+    let (metadata, file_id, content) = (session.metadata.clone(), session.file_id.clone(), std::mem::take(&mut session.content));
+    let file = match file_id {
+        Some(file_id) => {
+            let file = state.files.get_mut(&file_id).unwrap();
+            file.content = content;
+            file.modified_time = chrono::Utc::now().to_rfc3339();
+            let file = to_json(file);
+            state.changes.push(file_id);
+            file
+        },
+        None => {
+            create_file(state, &metadata, content);
+            to_json(&state.files[metadata["id"].as_str().unwrap()])
+        }
+    };
+
+    state.sessions.get_mut(id).unwrap().complete = Some(file.clone());
+    HttpResponse::Ok().json(file)
+}
+
+/// Hand out a new file ID
+fn new_id(state: &mut State) -> String {
+    state.ids += 1;
+    format!("fake-id-{:06}", state.ids)
+}
+
+/// Split a `multipart/related` upload into its metadata and its content
+fn multipart(req: &HttpRequest, body: &[u8]) -> Option<(Value, Vec<u8>)> {
+    let boundary = req.headers().get_all(header::CONTENT_TYPE)
+        .filter_map(|value| value.to_str().ok())
+        .find_map(|value| value.split("boundary=").nth(1))?
+        .trim_matches('"')
+        .to_string();
+
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let mut parts = Vec::new();
+    let mut rest = body;
+    while let Some(start) = find(rest, &delimiter) {
+        rest = &rest[start + delimiter.len()..];
+        if rest.starts_with(b"--") {
+            break;
+        }
+
+        let end = find(rest, &delimiter)?;
+        let part = &rest[..end];
+        let headers_end = find(part, b"\r\n\r\n")?;
+        let content = &part[headers_end + 4..];
+        parts.push(content.strip_suffix(b"\r\n").unwrap_or(content).to_vec());
+    }
+
+    if parts.len() != 2 {
+        return None;
+    }
+
+    let content = parts.pop()?;
+    let metadata = serde_json::from_slice(&parts.pop()?).ok()?;
+    Some((metadata, content))
+}
+
+/// Get the claims of a JWT with a header, claims and a signature
+fn jwt_claims(jwt: &str) -> Option<Value> {
+    let parts: Vec<&str> = jwt.split('.').collect();
+    if parts.len() != 3 {
+        return None;
+    }
+
+    serde_json::from_slice(&base64::decode_config(parts[1], base64::URL_SAFE_NO_PAD).ok()?).ok()
+}
+
+/// Find a file or folder by its path from the folder or shared drive with ID `root`. Trashed files are only found if there is no other
+fn lookup(state: &State, root: &str, path: &str) -> Option<FakeFile> {
+    let mut parent = root.to_string();
+    let mut found = None;
+    for name in path.split('/') {
+        let file = state.files.values()
+            .filter(|file| file.name == name && file.parents.contains(&parent))
+            .min_by_key(|file| file.trashed)?;
+
+        parent = file.id.clone();
+        found = Some(file.clone());
+    }
+
+    found
+}
+
+/// Find the first position of `needle` in `haystack`
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// Describe a file as the Drive API would
+fn to_json(file: &FakeFile) -> Value {
+    let mut json = json!({
+        "kind": "drive#file",
+        "id": file.id,
+        "name": file.name,
+        "mimeType": file.mime_type,
+        "modifiedTime": file.modified_time,
+        "parents": file.parents,
+        "trashed": file.trashed
+    });
+
+    if file.mime_type != FOLDER_MIME_TYPE {
+        json["md5Checksum"] = json!(format!("{:x}", md5::Md5::digest(&file.content)));
+        json["size"] = json!(file.content.len().to_string());
+    }
+
+    json
+}
+
+/// Respond with a 404 error for the file with ID `id`
+fn not_found(id: &str) -> HttpResponse {
+    error(StatusCode::NOT_FOUND, "notFound", &format!("File not found: {}.", id))
+}
+
+/// Respond with an error as the Google APIs would
+fn error(status: StatusCode, reason: &str, message: &str) -> HttpResponse {
+    HttpResponse::build(status).json(json!({
+        "error": {
+            "code": status.as_u16(),
+            "message": message,
+            "errors": [{ "domain": "global", "reason": reason, "message": message }]
+        }
+    }))
+}
@@ -0,0 +1,115 @@
+//! Helpers shared by the end to end tests, which run `gsync` against a fake Google Drive
+// Not every test uses every helper
+#![allow(dead_code)]
+
+pub mod fake_drive;
+
+use fake_drive::FakeDrive;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+
+/// Struct describing a GSync installation with its own home directory, syncing to a fake Drive
+pub struct TestEnv {
+    /// The directory everything of the test is stored in. The home directory of GSync, the files to sync, restored files
+    pub dir:    PathBuf,
+
+    /// The fake Drive GSync syncs to
+    pub drive:  FakeDrive
+}
+
+impl TestEnv {
+    /// Set up GSync to sync the directory `files` in the test directory, without logging in
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("gsync-it-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("files")).unwrap();
+
+        let env = Self {
+            dir,
+            drive:  FakeDrive::start()
+        };
+
+        let files = env.path("files");
+        env.gsync(&["config", "--id", "client-id", "--secret", "client-secret", "--files", files.to_str().unwrap()]);
+        env
+    }
+
+    /// Set up GSync like `new`, logged in with an access token which has expired
+    pub fn logged_in(name: &str) -> Self {
+        let env = Self::new(name);
+        let conn = rusqlite::Connection::open(env.dir.join(".gsync").join("data.db3")).unwrap();
+        conn.execute("INSERT INTO user (refresh_token, access_token, expiry) VALUES ('refresh-token', 'expired-token', 0)", rusqlite::named_params! {}).unwrap();
+
+        env
+    }
+
+    /// Count the rows of a table in the database of GSync which belong to the profile `profile`
+    pub fn rows(&self, table: &str, profile: &str) -> i64 {
+        let conn = rusqlite::Connection::open(self.dir.join(".gsync").join("data.db3")).unwrap();
+        conn.query_row(&format!("SELECT COUNT(*) FROM {} WHERE profile = :profile", table), rusqlite::named_params! { ":profile": profile }, |row| row.get(0)).unwrap()
+    }
+
+    /// Get the path of `path` in the test directory
+    pub fn path(&self, path: &str) -> PathBuf {
+        self.dir.join(path)
+    }
+
+    /// Write a file in the test directory, creating its parent directories
+    pub fn write(&self, path: &str, contents: &str) {
+        let path = self.path(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+
+    /// Run gsync with the arguments `args`, and assert it succeeded
+    pub fn gsync(&self, args: &[&str]) -> Output {
+        self.gsync_with_input(args, "")
+    }
+
+    /// Run gsync with the arguments `args` and `input` on stdin, and assert it succeeded
+    pub fn gsync_with_input(&self, args: &[&str], input: &str) -> Output {
+        let output = self.run(args, input);
+        assert!(output.status.success(), "gsync {:?} failed:\n{}{}", args, String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+        output
+    }
+
+    /// Run gsync with the arguments `args` and `input` on stdin, whether it succeeds or not
+    pub fn run(&self, args: &[&str], input: &str) -> Output {
+        let mut child = self.spawn(args);
+        child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+        child.wait_with_output().unwrap()
+    }
+
+    /// Start gsync with the arguments `args`, with piped stdin, stdout and stderr
+    pub fn spawn(&self, args: &[&str]) -> Child {
+        Command::new(env!("CARGO_BIN_EXE_gsync"))
+            .args(args)
+            .env("HOME", &self.dir)
+            .env("GSYNC_GOOGLE_APIS_URL", &self.drive.url)
+            .env("GSYNC_OAUTH_URL", &self.drive.url)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap()
+    }
+
+    /// Get the contents of a file in the fake Drive, by its path from the GSync folder
+    pub fn remote_contents(&self, path: &str) -> String {
+        let file = self.drive.find(&format!("GSync/{}", path)).unwrap_or_else(|| panic!("'{}' is not in Drive", path));
+        assert!(!file.trashed, "'{}' is trashed", path);
+        String::from_utf8(file.content).unwrap()
+    }
+}
+
+impl Drop for TestEnv {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Get the contents of a local file
+pub fn contents(path: &Path) -> String {
+    std::fs::read_to_string(path).unwrap()
+}
@@ -0,0 +1,116 @@
+//! End to end tests of logging in, against a fake Google OAuth2 API
+
+mod common;
+
+use common::TestEnv;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use common::fake_drive::{AUTHORIZATION_CODE, USER_CODE, DESKTOP_CLIENT_ID};
+
+#[test]
+fn login_without_browser() {
+    let env = TestEnv::new("login");
+
+    let output = env.gsync_with_input(&["login", "--no-browser"], &format!("{}\n", AUTHORIZATION_CODE));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Login successful!"));
+    assert_eq!(1, env.drive.token_requests());
+
+    // The access token was stored, so it doesn't have to be requested again
+    env.write("files/a.txt", "a");
+    env.gsync(&["sync"]);
+    assert_eq!("a", env.remote_contents("files/a.txt"));
+    assert_eq!(1, env.drive.token_requests());
+}
+
+#[test]
+fn login_with_browser() {
+    let env = TestEnv::new("browser");
+    let mut child = env.spawn(&["login"]);
+
+    // Wait for the authentication URL, which holds the port GSync listens on and the state
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let auth_uri = loop {
+        let mut line = String::new();
+        assert!(stdout.read_line(&mut line).unwrap() > 0, "No authentication URL was printed");
+        if line.starts_with("https://") {
+            break line;
+        }
+    };
+
+    let param = |name: &str| auth_uri.split(&format!("{}=", name)).nth(1).unwrap().split(&['&', '\n'][..]).next().unwrap().to_string();
+    let port = param("redirect_uri").trim_start_matches("http%3A%2F%2Flocalhost%3A").to_string();
+    let state = param("state");
+
+    // What the browser does after logging in
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+    write!(stream, "GET /?state={}&code={} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n", state, AUTHORIZATION_CODE.replace('/', "%2F")).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.contains("You can now close this tab."));
+
+    let mut rest = String::new();
+    stdout.read_to_string(&mut rest).unwrap();
+    assert!(child.wait().unwrap().success());
+    assert!(rest.contains("Login successful!"));
+    assert_eq!(1, env.drive.token_requests());
+}
+
+#[test]
+fn login_with_device_code() {
+    let env = TestEnv::new("device");
+
+    let output = env.gsync(&["login", "--device"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains(USER_CODE));
+    assert!(stdout.contains("Login successful!"));
+
+    env.gsync(&["drives"]);
+    assert_eq!(3, env.drive.token_requests());
+}
+
+#[test]
+fn device_login_explains_the_required_client_type() {
+    let env = TestEnv::new("device-desktop-client");
+    env.gsync(&["config", "--id", DESKTOP_CLIENT_ID]);
+
+    let output = env.run(&["login", "--device"], "");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("'TVs and Limited Input devices'"));
+}
+
+#[test]
+fn whoami_and_logout() {
+    let env = TestEnv::logged_in("logout");
+
+    let output = env.gsync(&["whoami"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Logged in as Fake User <user@example.com>"));
+    assert!(stdout.contains("The access token expires at"));
+
+    env.gsync(&["logout"]);
+    assert_eq!(vec!["refresh-token".to_string()], env.drive.revoked_tokens());
+    assert!(!env.run(&["whoami"], "").status.success());
+}
+
+#[test]
+fn logout_forgets_the_state_of_the_profile() {
+    let env = TestEnv::logged_in("logout-state");
+    env.write("files/a.txt", "a");
+    env.write("work/b.txt", "b");
+
+    let work = env.path("work");
+    env.gsync(&["--profile", "work", "config", "--id", "work-id", "--secret", "work-secret", "--files", work.to_str().unwrap(), "--root-folder", "Work"]);
+    env.gsync(&["--profile", "work", "login", "--device"]);
+    env.gsync(&["sync"]);
+    env.gsync(&["--profile", "work", "sync"]);
+    assert!(env.rows("sync_state", "default") > 0);
+
+    env.gsync(&["logout"]);
+
+    for table in &["user", "sync_state", "change_tokens", "upload_sessions"] {
+        assert_eq!(0, env.rows(table, "default"), "'{}' still has rows of the logged out profile", table);
+    }
+
+    assert_eq!(1, env.rows("user", "work"));
+    assert!(env.rows("sync_state", "work") > 0);
+}
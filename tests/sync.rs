@@ -0,0 +1,352 @@
+//! End to end tests, running `gsync` against a fake Google Drive
+
+mod common;
+
+use common::{TestEnv, contents};
+use common::fake_drive::FOLDER_MIME_TYPE;
+
+#[test]
+fn sync_uploads_files_and_folders() {
+    let env = TestEnv::logged_in("upload");
+    env.write("files/a.txt", "a");
+    env.write("files/docs/b.txt", "b");
+
+    env.gsync(&["sync"]);
+
+    assert_eq!(FOLDER_MIME_TYPE, env.drive.find("GSync/files/docs").unwrap().mime_type);
+    assert_eq!("a", env.remote_contents("files/a.txt"));
+    assert_eq!("b", env.remote_contents("files/docs/b.txt"));
+
+    // The expired access token is refreshed once, and the new one is stored for the next run
+    assert_eq!(1, env.drive.token_requests());
+    env.gsync(&["sync"]);
+    assert_eq!(1, env.drive.token_requests());
+}
+
+#[test]
+fn sync_updates_changed_files_and_trashes_removed_files() {
+    let env = TestEnv::logged_in("update");
+    env.write("files/a.txt", "a");
+    env.write("files/b.txt", "b");
+    env.gsync(&["sync", "--jobs", "2"]);
+
+    env.write("files/a.txt", "changed");
+    std::fs::remove_file(env.path("files/b.txt")).unwrap();
+    env.gsync(&["sync", "--delete"]);
+
+    assert_eq!("changed", env.remote_contents("files/a.txt"));
+    assert!(env.drive.find("GSync/files/b.txt").unwrap().trashed);
+}
+
+#[test]
+fn sync_permanently_deletes_removed_files() {
+    let env = TestEnv::logged_in("permanent");
+    env.write("files/a.txt", "a");
+    env.write("files/docs/b.txt", "b");
+    env.gsync(&["sync"]);
+
+    std::fs::remove_file(env.path("files/a.txt")).unwrap();
+    std::fs::remove_dir_all(env.path("files/docs")).unwrap();
+    env.gsync(&["sync", "--delete", "--permanent"]);
+
+    assert!(env.drive.find("GSync/files/a.txt").is_none());
+    assert!(env.drive.find("GSync/files/docs").is_none());
+}
+
+#[test]
+fn sync_deletes_removed_inputs() {
+    let env = TestEnv::logged_in("removed-input");
+    env.write("files/a.txt", "a");
+    env.write("other/b.txt", "b");
+    let inputs = format!("{},{}", env.path("files").to_str().unwrap(), env.path("other").to_str().unwrap());
+    env.gsync(&["config", "--files", &inputs]);
+    env.gsync(&["sync"]);
+
+    std::fs::remove_dir_all(env.path("other")).unwrap();
+    env.gsync(&["sync", "--delete"]);
+
+    assert!(env.drive.find("GSync/other").unwrap().trashed);
+    assert_eq!("a", env.remote_contents("files/a.txt"));
+}
+
+#[test]
+fn restore_downloads_synced_files() {
+    let env = TestEnv::logged_in("restore");
+    env.write("files/a.txt", "a");
+    env.write("files/docs/b.txt", "b");
+    env.gsync(&["sync"]);
+
+    let target = env.path("restored");
+    env.gsync(&["restore", "--to", target.to_str().unwrap()]);
+
+    assert_eq!("a", common::contents(&target.join("files").join("a.txt")));
+    assert_eq!("b", common::contents(&target.join("files").join("docs").join("b.txt")));
+}
+
+#[test]
+fn drives_lists_shared_drives() {
+    let env = TestEnv::logged_in("drives");
+    env.drive.add_shared_drive("drive-id", "Team");
+
+    let output = env.gsync(&["drives"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Shared drive 'Team' with identifier 'drive-id'"));
+}
+
+#[test]
+fn sync_with_service_account() {
+    let env = TestEnv::new("service-account");
+    let private_key = openssl::rsa::Rsa::generate(2048).unwrap().private_key_to_pem().unwrap();
+    let key = serde_json::json!({
+        "type": "service_account",
+        "client_email": "backup@project.iam.gserviceaccount.com",
+        "private_key": String::from_utf8(private_key).unwrap()
+    });
+    env.write("key.json", &key.to_string());
+    env.gsync(&["config", "--service-account", env.path("key.json").to_str().unwrap()]);
+
+    env.write("files/a.txt", "a");
+    env.gsync(&["sync"]);
+
+    assert_eq!("a", env.remote_contents("files/a.txt"));
+    assert_eq!(1, env.drive.token_requests());
+}
+
+#[test]
+fn profiles_have_their_own_configuration_and_login() {
+    let env = TestEnv::logged_in("profiles");
+    env.write("files/a.txt", "a");
+    env.write("work/b.txt", "b");
+
+    let work = env.path("work");
+    env.gsync(&["--profile", "work", "config", "--id", "work-id", "--secret", "work-secret", "--files", work.to_str().unwrap(), "--root-folder", "Work"]);
+    assert!(!env.run(&["--profile", "work", "sync"], "").status.success());
+    env.gsync(&["--profile", "work", "login", "--device"]);
+
+    env.gsync(&["sync"]);
+    env.gsync(&["--profile", "work", "sync"]);
+
+    assert_eq!("a", env.remote_contents("files/a.txt"));
+    assert_eq!("b", String::from_utf8(env.drive.find("Work/work/b.txt").unwrap().content).unwrap());
+    assert!(env.drive.find("GSync/work").is_none());
+
+    // Logging out of one profile keeps the other logged in
+    env.gsync(&["--profile", "work", "logout"]);
+    env.gsync(&["sync"]);
+}
+
+#[test]
+fn profiles_syncing_to_the_same_root_keep_their_own_state() {
+    let env = TestEnv::logged_in("profiles-same-root");
+    env.write("files/a.txt", "a");
+
+    let files = env.path("files");
+    env.gsync(&["--profile", "work", "config", "--id", "work-id", "--secret", "work-secret", "--files", files.to_str().unwrap()]);
+    env.gsync(&["--profile", "work", "login", "--device"]);
+
+    env.gsync(&["sync", "--two-way"]);
+    env.gsync(&["--profile", "work", "sync", "--two-way"]);
+
+    // Neither profile replaced the state or change token of the other
+    for profile in &["default", "work"] {
+        assert_eq!(2, env.rows("sync_state", profile), "Sync state of profile '{}'", profile);
+        assert_eq!(1, env.rows("change_tokens", profile), "Change token of profile '{}'", profile);
+    }
+
+    let output = env.gsync(&["sync", "--two-way"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Listing changes in Drive"), "{}", stdout);
+    assert!(!stdout.contains("Querying Drive"), "{}", stdout);
+}
+
+#[test]
+fn sync_jobs_have_their_own_destination() {
+    let env = TestEnv::logged_in("jobs");
+    env.drive.add_shared_drive("drive-id", "Team");
+    env.write("files/a.txt", "a");
+    env.write("notes/todo.txt", "todo");
+    env.write("projects/gsync/main.rs", "fn main() {}");
+    env.write("projects/gsync/target/gsync", "binary");
+
+    let (notes, projects) = (env.path("notes"), env.path("projects"));
+    env.gsync(&["job", "add", "notes", "--source", notes.to_str().unwrap(), "--to", "Backups/Notes"]);
+    env.gsync(&["job", "add", "projects", "--source", projects.to_str().unwrap(), "--to", "Projects", "--drive", "drive-id", "--exclude", "target/"]);
+    assert!(!env.run(&["job", "add", "root", "--source", notes.to_str().unwrap(), "--to-id", "root"], "").status.success());
+
+    // A dry run doesn't create the destination folders
+    env.gsync(&["sync", "--dry-run"]);
+    assert!(env.drive.find("Backups").is_none());
+
+    // Without a job name, the configured files and all jobs are synced
+    env.gsync(&["sync"]);
+
+    assert_eq!("a", env.remote_contents("files/a.txt"));
+    assert_eq!("todo", String::from_utf8(env.drive.find("Backups/Notes/todo.txt").unwrap().content).unwrap());
+    assert_eq!("fn main() {}", String::from_utf8(env.drive.find_in("drive-id", "Projects/gsync/main.rs").unwrap().content).unwrap());
+    assert!(env.drive.find_in("drive-id", "Projects/gsync/target").is_none());
+
+    // With a job name, only that job is run
+    env.write("files/a.txt", "changed");
+    env.write("notes/todo.txt", "done!");
+    env.gsync(&["sync", "notes"]);
+
+    assert_eq!("a", env.remote_contents("files/a.txt"));
+    assert_eq!("done!", String::from_utf8(env.drive.find("Backups/Notes/todo.txt").unwrap().content).unwrap());
+
+    env.gsync(&["job", "remove", "notes"]);
+    assert!(!env.run(&["sync", "notes"], "").status.success());
+}
+
+#[test]
+fn included_files_in_ignored_directories_are_synced() {
+    let env = TestEnv::logged_in("include-ignored");
+    env.write("files/.gitignore", "build/");
+    env.write("files/build/keep.txt", "keep");
+    env.write("files/build/drop.txt", "drop");
+    env.write("files/build/debug/drop.txt", "drop");
+    env.gsync(&["config", "--include", "build/keep.txt"]);
+
+    env.gsync(&["sync"]);
+
+    assert_eq!("keep", env.remote_contents("files/build/keep.txt"));
+    assert!(env.drive.find("GSync/files/build/drop.txt").is_none());
+    assert!(env.drive.find("GSync/files/build/debug").is_none());
+}
+
+#[test]
+fn interrupted_large_upload_is_resumed() {
+    let env = TestEnv::logged_in("resumable");
+    let content = "0123456789abcdef".repeat(6 * 1024 * 1024 / 16);
+    env.write("files/large.bin", &content);
+    env.gsync(&["config", "--max-attempts", "1"]);
+
+    // The first chunk fails halfway, which stops the sync
+    env.drive.fail_chunks(1);
+    assert!(!env.run(&["sync"], "").status.success());
+    assert!(env.drive.find("GSync/files/large.bin").is_none());
+
+    let output = env.gsync(&["sync"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Resuming upload"));
+    assert!(env.remote_contents("files/large.bin") == content, "The uploaded content differs");
+}
+
+#[test]
+fn failed_chunk_is_resumed_from_the_received_offset() {
+    let env = TestEnv::logged_in("resumable-retry");
+    let content = "0123456789abcdef".repeat(6 * 1024 * 1024 / 16);
+    env.write("files/large.bin", &content);
+
+    // Half of the first chunk is received. Sending the whole chunk again is rejected, so the status has to be queried
+    env.drive.fail_chunks(1);
+    let output = env.gsync(&["sync"]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("resuming in"));
+    assert!(env.remote_contents("files/large.bin") == content, "The uploaded content differs");
+}
+
+#[test]
+fn continue_on_error_syncs_the_other_files() {
+    for args in &[&["sync", "--continue-on-error"][..], &["sync", "--continue-on-error", "--jobs", "4"][..]] {
+        let env = TestEnv::logged_in(&format!("continue-on-error-{}", args.len()));
+        env.write("files/a.txt", "a");
+        env.write("files/dir/c.txt", "c");
+        env.write("files/z.txt", "z");
+
+        // Reading a dangling symlink fails
+        std::os::unix::fs::symlink(env.path("missing.txt"), env.path("files/b.txt")).unwrap();
+
+        let output = env.run(args, "");
+        assert!(!output.status.success());
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("Error: 1 entries failed to sync:"), "No summary was printed: {}", stderr);
+        assert!(stderr.contains("b.txt"));
+
+        assert_eq!("a", env.remote_contents("files/a.txt"));
+        assert_eq!("c", env.remote_contents("files/dir/c.txt"));
+        assert_eq!("z", env.remote_contents("files/z.txt"));
+        assert!(env.drive.find("GSync/files/b.txt").is_none());
+    }
+}
+
+#[test]
+fn continue_on_error_skips_directories_which_cant_be_traversed() {
+    let env = TestEnv::logged_in("continue-on-error-traverse");
+    env.write("files/a.txt", "a");
+    env.write("files/sub/b.txt", "b");
+    env.gsync(&["sync"]);
+
+    // An ignore file which isn't valid UTF-8 can't be read
+    std::fs::write(env.path("files/sub/.gitignore"), b"\xff\xfe").unwrap();
+    env.write("files/c.txt", "c");
+    let gitignore = env.path("files/sub/.gitignore");
+
+    let output = env.run(&["sync"], "");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains(gitignore.to_str().unwrap()));
+    assert!(env.drive.find("GSync/files/c.txt").is_none());
+
+    let output = env.run(&["sync", "--continue-on-error", "--delete"], "");
+    assert!(!output.status.success());
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Error: 1 entries failed to sync:"), "No summary was printed: {}", stderr);
+    assert!(stderr.contains(gitignore.to_str().unwrap()), "{}", stderr);
+
+    // The rest is synced, and the copy of the directory which was skipped is left alone
+    assert_eq!("c", env.remote_contents("files/c.txt"));
+    assert_eq!("b", env.remote_contents("files/sub/b.txt"));
+}
+
+#[test]
+fn names_with_quotes_are_escaped_in_queries() {
+    let env = TestEnv::logged_in("escaping");
+    env.write("files/a.txt", "a");
+    env.write("notes/todo.txt", "todo");
+    env.gsync(&["config", "--root-folder", "Bob's \\ files"]);
+
+    let notes = env.path("notes");
+    env.gsync(&["job", "add", "notes", "--source", notes.to_str().unwrap(), "--to", "Bob's/Notes"]);
+
+    // The second sync finds the folders created by the first one
+    env.gsync(&["sync"]);
+    env.write("files/a.txt", "changed");
+    env.gsync(&["sync"]);
+
+    assert_eq!("changed", String::from_utf8(env.drive.find("Bob's \\ files/files/a.txt").unwrap().content).unwrap());
+    assert_eq!("todo", String::from_utf8(env.drive.find("Bob's/Notes/todo.txt").unwrap().content).unwrap());
+}
+
+#[test]
+fn restore_from_local_directory_needs_no_configuration() {
+    let env = TestEnv::new("restore-local");
+    env.write("files/a.txt", "a");
+
+    let target = env.path("target");
+    env.gsync(&["sync", "--local", target.to_str().unwrap()]);
+
+    // A profile which was never configured
+    let restored = env.path("restored");
+    env.gsync(&["--profile", "other", "restore", "--local", target.to_str().unwrap(), "--to", restored.to_str().unwrap()]);
+    assert_eq!("a", contents(&restored.join("files").join("a.txt")));
+}
+
+#[test]
+fn sync_to_local_directory_compares_checksums() {
+    let env = TestEnv::new("local");
+    env.write("files/a.txt", "new");
+    env.write("files/b.txt", "same");
+    env.write("target/files/a.txt", "old");
+    env.write("target/files/b.txt", "same");
+
+    let target = env.path("target");
+    let output = env.gsync(&["sync", "--local", target.to_str().unwrap()]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    // Both files have the same size as the existing copies, so only the checksum tells them apart
+    assert!(stdout.contains("Updating file 'a.txt'"), "{}", stdout);
+    assert!(stdout.contains("File 'b.txt' is up-to-date."), "{}", stdout);
+    assert_eq!("new", contents(&env.path("target/files/a.txt")));
+
+    // Nothing refers to Google Drive
+    assert!(stdout.contains(&format!("The target directory '{}' exists.", target.to_str().unwrap())), "{}", stdout);
+    assert!(!stdout.contains("Drive"), "{}", stdout);
+}
@@ -0,0 +1,82 @@
+//! End to end tests of two-way syncing, running `gsync sync --two-way` against a fake Google Drive
+
+mod common;
+
+use common::TestEnv;
+
+/// Set up GSync with `files/a.txt` and `files/b.txt` synced both ways
+fn synced(name: &str) -> TestEnv {
+    let env = TestEnv::logged_in(name);
+    env.write("files/a.txt", "a");
+    env.write("files/b.txt", "b");
+    env.gsync(&["sync", "--two-way"]);
+
+    env
+}
+
+#[test]
+fn two_way_sync_pulls_changes_from_drive() {
+    let env = synced("two-way-pull");
+    env.drive.write("GSync/files/a.txt", "changed in Drive");
+    env.drive.write("GSync/files/new.txt", "new");
+    let output = env.gsync(&["sync", "--two-way"]);
+
+    // The second run only lists the folders which changed since the first
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Listing changes in Drive"));
+    assert_eq!("changed in Drive", common::contents(&env.path("files/a.txt")));
+    assert_eq!("new", common::contents(&env.path("files/new.txt")));
+    assert_eq!("b", common::contents(&env.path("files/b.txt")));
+}
+
+#[test]
+fn two_way_sync_pushes_local_changes() {
+    let env = synced("two-way-push");
+    env.write("files/a.txt", "changed locally");
+    env.gsync(&["sync", "--two-way"]);
+
+    assert_eq!("changed locally", env.remote_contents("files/a.txt"));
+    assert_eq!("changed locally", common::contents(&env.path("files/a.txt")));
+}
+
+#[test]
+fn two_way_sync_keeps_both_versions_on_conflict() {
+    let env = synced("two-way-conflict");
+    env.write("files/a.txt", "changed locally");
+    env.drive.write("GSync/files/a.txt", "changed in Drive");
+    env.gsync(&["sync", "--two-way"]);
+
+    // The local version wins, the version from Drive is kept next to it on both sides
+    assert_eq!("changed locally", common::contents(&env.path("files/a.txt")));
+    assert_eq!("changed locally", env.remote_contents("files/a.txt"));
+
+    let conflict = std::fs::read_dir(env.path("files")).unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .find(|name| name.starts_with("a.conflict-"))
+        .expect("The version from Drive wasn't saved");
+    assert_eq!("changed in Drive", common::contents(&env.path(&format!("files/{}", conflict))));
+    assert_eq!("changed in Drive", env.remote_contents(&format!("files/{}", conflict)));
+}
+
+#[test]
+fn two_way_sync_removes_files_removed_from_drive() {
+    let env = synced("two-way-remote-delete");
+    env.drive.trash("GSync/files/b.txt");
+
+    // Without --delete the local file would be uploaded again. A dry run doesn't forget that it was removed from Drive
+    env.gsync(&["sync", "--two-way", "--dry-run"]);
+    assert!(env.path("files/b.txt").exists());
+
+    env.gsync(&["sync", "--two-way", "--delete"]);
+    assert!(!env.path("files/b.txt").exists());
+    assert_eq!("a", common::contents(&env.path("files/a.txt")));
+}
+
+#[test]
+fn two_way_sync_rejects_jobs() {
+    let env = synced("two-way-jobs");
+
+    // A two-way sync runs one file at a time, so --jobs would be ignored
+    let output = env.run(&["sync", "--two-way", "--jobs", "4"], "");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("cannot be used with"));
+}